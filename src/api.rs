@@ -1,33 +1,110 @@
 use std::{
-    io,
-    ops::{Add, Mul, Sub},
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Read},
+    marker::PhantomData,
+    ops::{Add, Index, IndexMut, Mul, Sub},
+    sync::{Arc, Mutex},
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
-use serde_repr::Deserialize_repr;
 use thiserror::Error;
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
+
+use crate::cache::TtlCache;
+use crate::claims::{self, TokenClaims};
+use crate::config::ClientConfig;
+use crate::diff::StateDiff;
+use crate::etag::EtagCache;
+use crate::fleet::{self, OngoingSessions};
+use crate::ids::{ChargerId, CircuitId, SiteId};
+use crate::installation::Installation;
+use crate::money::Money;
+use crate::observation::{
+    decode_update, Event, ObservationSample, ParseError, ProductUpdate, ReasonForNoCurrent,
+};
+use crate::ratelimit::{RateLimiter, SharedRateBudget};
+use crate::retry::RetryPolicy;
+use crate::schema::{SchemaEvent, SchemaTracker};
+use crate::secret::{redact, SecretString};
+use crate::tokenstore::{TokenStore, TokenStoreError};
+use crate::units::{Amperes, KilowattHours, Kilowatts};
+use crate::voltage::VoltageMatrix;
+
+/// Delay between two state requests of [`Charger::wait_for_op_mode`]
+const OP_MODE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many times a request rejected with 429 Too Many Requests is sent
+/// again after waiting as told by the server
+const MAX_RATE_LIMITED_RETRIES: u32 = 3;
+
+/// Longest `Retry-After` delay honored, longer ones fail the request
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+type RefreshCallback = Box<dyn FnMut(&TokenSnapshot) + Send>;
+type CredentialsCallback = Box<dyn FnMut() -> Option<Credentials> + Send>;
+type PauseCallback = Box<dyn FnMut(CircuitId, Option<&Triphase>) + Send>;
 
 pub struct Context {
-    auth_header: String,
-    refresh_token: String,
+    config: ClientConfig,
+    agent: ureq::Agent,
+    auth_header: SecretString,
+    refresh_token: SecretString,
     token_expiration: Instant,
-    on_refresh: Option<Box<dyn FnMut(&mut Self) + Send>>,
+    token_issued: SystemTime,
+    rate_budget: Option<SharedRateBudget>,
+    rate_limiter: Option<RateLimiter>,
+    retry: Option<RetryPolicy>,
+    etags: Option<EtagCache>,
+    ttl_cache: Option<TtlCache>,
+    topology: Option<Installation>,
+    on_refresh: Option<RefreshCallback>,
+    token_store: Option<Box<dyn TokenStore>>,
+    credentials: Option<CredentialsCallback>,
+    paused_circuits: HashMap<CircuitId, Triphase>,
+    on_pause: Option<PauseCallback>,
+    schema: SchemaTracker,
+
+    /// Context of the [`crate::shared::SharedContext`] this one was forked
+    /// from, which refreshes the tokens on its behalf
+    shared: Option<Arc<Mutex<Context>>>,
+    #[cfg(feature = "debug-bodies")]
+    body_capture: Option<BodyCapture>,
 }
 
 impl std::fmt::Debug for Context {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Context")
-            .field("auth_header", &"<secret>")
-            .field("refresh_token", &"<secret>")
+            .field("auth_header", &self.auth_header)
+            .field("refresh_token", &self.refresh_token)
             .field("token_expiration", &self.token_expiration)
             .field("on_refresh", &"[closure]")
             .finish()
     }
 }
 
-const API_BASE: &str = "https://api.easee.com/api/";
+/// Formats of the timestamps carrying an offset, by priority. `%.f` also
+/// matches timestamps without fractional seconds.
+const OFFSET_FORMATS: &[&str] = &["%+", "%Y-%m-%d %H:%M:%S%.f%#z"];
+
+/// Formats of the timestamps without an offset, by priority
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+];
+
+fn parse_with_offset(s: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    OFFSET_FORMATS
+        .iter()
+        .find_map(|f| chrono::DateTime::parse_from_str(s, f).ok())
+}
+
+fn parse_naive(s: &str) -> Option<chrono::NaiveDateTime> {
+    NAIVE_FORMATS
+        .iter()
+        .find_map(|f| chrono::NaiveDateTime::parse_from_str(s, f).ok())
+}
 
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct NaiveDateTime(pub chrono::NaiveDateTime);
@@ -35,10 +112,20 @@ pub struct NaiveDateTime(pub chrono::NaiveDateTime);
 impl<'de> Deserialize<'de> for NaiveDateTime {
     fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
         use serde::de::Error;
-        let s = <&str as Deserialize>::deserialize(d)?;
-        let dt = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
-            .map_err(D::Error::custom)?;
-        Ok(NaiveDateTime(dt))
+        // Owned, as escaped strings and readers can't lend borrowed ones
+        let s = String::deserialize(d)?;
+        let s = s.trim();
+        // Some fields sometimes carry an offset, keep their local time
+        parse_naive(s)
+            .or_else(|| parse_with_offset(s).map(|dt| dt.naive_local()))
+            .map(NaiveDateTime)
+            .ok_or_else(|| D::Error::custom(format!("invalid timestamp `{s}`")))
+    }
+}
+
+impl Serialize for NaiveDateTime {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(&self.0.format("%Y-%m-%dT%H:%M:%S%.f"))
     }
 }
 
@@ -48,30 +135,158 @@ pub struct UtcDateTime(pub chrono::DateTime<chrono::Utc>);
 impl<'de> Deserialize<'de> for UtcDateTime {
     fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
         use serde::de::Error;
-        let s = <&str as Deserialize>::deserialize(d)?;
-        let dt = chrono::DateTime::parse_from_str(s, "%+")
-            .map_err(D::Error::custom)?
-            .to_utc();
-        Ok(UtcDateTime(dt))
+        let s = String::deserialize(d)?;
+        let s = s.trim();
+        parse_utc(s).ok_or_else(|| D::Error::custom(format!("invalid timestamp `{s}`")))
+    }
+}
+
+fn parse_utc(s: &str) -> Option<UtcDateTime> {
+    // Timestamps without an offset are in UTC
+    parse_with_offset(s)
+        .map(|dt| dt.to_utc())
+        .or_else(|| parse_naive(s).map(|dt| dt.and_utc()))
+        .map(UtcDateTime)
+}
+
+/// Optional timestamps come as `null`, an empty string, or may be missing
+fn deserialize_optional_utc<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<UtcDateTime>, D::Error> {
+    use serde::de::Error;
+    match Option::<String>::deserialize(d)? {
+        Some(s) if !s.trim().is_empty() => parse_utc(s.trim())
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid timestamp `{s}`"))),
+        _ => Ok(None),
+    }
+}
+
+impl Serialize for UtcDateTime {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.0.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+    }
+}
+
+impl From<chrono::DateTime<chrono::Utc>> for UtcDateTime {
+    fn from(value: chrono::DateTime<chrono::Utc>) -> Self {
+        UtcDateTime(value)
+    }
+}
+
+impl UtcDateTime {
+    /// Current time
+    pub fn now() -> Self {
+        UtcDateTime(chrono::Utc::now())
+    }
+
+    /// Format the timestamp for use as a path segment in API URLs
+    pub(crate) fn to_url_param(self) -> String {
+        self.0.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+/// One of the three phases of a supply
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Phase {
+    L1,
+    L2,
+    L3,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::L1, Phase::L2, Phase::L3];
+}
+
+/// A current on each of the three phases
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Triphase {
-    pub phase1: f64,
-    pub phase2: f64,
-    pub phase3: f64,
+    pub phase1: Amperes,
+    pub phase2: Amperes,
+    pub phase3: Amperes,
+}
+
+impl Triphase {
+    pub fn new(phase1: Amperes, phase2: Amperes, phase3: Amperes) -> Self {
+        Triphase {
+            phase1,
+            phase2,
+            phase3,
+        }
+    }
+
+    /// Build a triphase current from the current of each phase
+    pub fn from_fn(mut f: impl FnMut(Phase) -> Amperes) -> Self {
+        Triphase::new(f(Phase::L1), f(Phase::L2), f(Phase::L3))
+    }
+
+    pub fn get(&self, phase: Phase) -> Amperes {
+        self[phase]
+    }
+
+    /// Currents of the phases, in order
+    pub fn iter(&self) -> impl Iterator<Item = Amperes> {
+        [self.phase1, self.phase2, self.phase3].into_iter()
+    }
+
+    /// Apply `f` to the current of each phase
+    pub fn map(self, mut f: impl FnMut(Amperes) -> Amperes) -> Self {
+        Triphase::from_fn(|p| f(self[p]))
+    }
+
+    /// Combine the currents of the same phase of two triphase currents
+    pub fn zip_with(self, other: Triphase, mut f: impl FnMut(Amperes, Amperes) -> Amperes) -> Self {
+        Triphase::from_fn(|p| f(self[p], other[p]))
+    }
+
+    /// Current of the most loaded phase
+    pub fn max(&self) -> Amperes {
+        Amperes(self.iter().map(|a| a.0).fold(f64::NEG_INFINITY, f64::max))
+    }
+
+    /// Current of the least loaded phase
+    pub fn min(&self) -> Amperes {
+        Amperes(self.iter().map(|a| a.0).fold(f64::INFINITY, f64::min))
+    }
+
+    /// Current summed over the three phases
+    pub fn sum(&self) -> Amperes {
+        self.iter().sum()
+    }
+
+    /// Whether the currents of all phases are within `tolerance` of each other
+    pub fn is_balanced(&self, tolerance: Amperes) -> bool {
+        self.max() - self.min() <= tolerance
+    }
+}
+
+impl Index<Phase> for Triphase {
+    type Output = Amperes;
+
+    fn index(&self, phase: Phase) -> &Amperes {
+        match phase {
+            Phase::L1 => &self.phase1,
+            Phase::L2 => &self.phase2,
+            Phase::L3 => &self.phase3,
+        }
+    }
+}
+
+impl IndexMut<Phase> for Triphase {
+    fn index_mut(&mut self, phase: Phase) -> &mut Amperes {
+        match phase {
+            Phase::L1 => &mut self.phase1,
+            Phase::L2 => &mut self.phase2,
+            Phase::L3 => &mut self.phase3,
+        }
+    }
 }
 
 impl Add<Triphase> for Triphase {
     type Output = Triphase;
 
     fn add(self, rhs: Triphase) -> Self::Output {
-        Triphase {
-            phase1: self.phase1 + rhs.phase1,
-            phase2: self.phase2 + rhs.phase2,
-            phase3: self.phase3 + rhs.phase3,
-        }
+        self.zip_with(rhs, Add::add)
     }
 }
 
@@ -79,11 +294,7 @@ impl Sub<Triphase> for Triphase {
     type Output = Triphase;
 
     fn sub(self, rhs: Triphase) -> Self::Output {
-        Triphase {
-            phase1: self.phase1 + rhs.phase1,
-            phase2: self.phase2 + rhs.phase2,
-            phase3: self.phase3 + rhs.phase3,
-        }
+        self.zip_with(rhs, Sub::sub)
     }
 }
 
@@ -91,82 +302,606 @@ impl Mul<f64> for Triphase {
     type Output = Triphase;
 
     fn mul(self, rhs: f64) -> Self::Output {
-        Triphase {
-            phase1: self.phase1 * rhs,
-            phase2: self.phase2 * rhs,
-            phase3: self.phase3 * rhs,
-        }
+        self.map(|a| a * rhs)
     }
 }
 
-impl From<f64> for Triphase {
-    fn from(value: f64) -> Self {
-        Triphase {
-            phase1: value,
-            phase2: value,
-            phase3: value,
-        }
+impl From<Amperes> for Triphase {
+    fn from(value: Amperes) -> Self {
+        Triphase::from_fn(|_| value)
     }
 }
 
-#[derive(Clone, Copy, Serialize)]
+/// Dynamic current of a circuit or charger, as sent to the API
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SetCurrent {
+    /// Minutes until the current falls back to its default. Zero keeps it
+    /// until changed; `None` leaves the choice to the server.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub time_to_live: Option<i32>,
     #[serde(flatten)]
     pub current: Triphase,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+impl SetCurrent {
+    pub fn builder() -> SetCurrentBuilder {
+        SetCurrentBuilder::default()
+    }
+
+    /// Check that the current of every phase is between zero and `rated`
+    pub fn validate(&self, rated: Amperes) -> Result<(), ApiError> {
+        match self
+            .current
+            .iter()
+            .find(|a| !(Amperes(0.0)..=rated).contains(a))
+        {
+            Some(current) => Err(ApiError::InvalidCurrent { current, rated }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Builder of a [`SetCurrent`]. Phases not set get no current.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SetCurrentBuilder {
+    current: Triphase,
+    time_to_live: Option<i32>,
+}
+
+impl SetCurrentBuilder {
+    pub fn phase(mut self, phase: Phase, current: Amperes) -> Self {
+        self.current[phase] = current;
+        self
+    }
+
+    pub fn phase1(self, current: Amperes) -> Self {
+        self.phase(Phase::L1, current)
+    }
+
+    pub fn phase2(self, current: Amperes) -> Self {
+        self.phase(Phase::L2, current)
+    }
+
+    pub fn phase3(self, current: Amperes) -> Self {
+        self.phase(Phase::L3, current)
+    }
+
+    /// Same current on all phases
+    pub fn all(mut self, current: Amperes) -> Self {
+        self.current = Triphase::from(current);
+        self
+    }
+
+    /// Fall back to the default current after `ttl`, rounded up to the
+    /// minute. A zero `ttl` keeps the current until changed.
+    pub fn time_to_live(mut self, ttl: Duration) -> Self {
+        let minutes = ttl.as_nanos().div_ceil(60_000_000_000);
+        self.time_to_live = Some(minutes.try_into().unwrap_or(i32::MAX));
+        self
+    }
+
+    pub fn build(self) -> SetCurrent {
+        SetCurrent {
+            time_to_live: self.time_to_live,
+            current: self.current,
+        }
+    }
+
+    /// Build the current, checking that it is within the rated current of `circuit`
+    pub fn build_for(self, circuit: &Circuit) -> Result<SetCurrent, ApiError> {
+        let current = self.build();
+        current.validate(Amperes(circuit.rated_current))?;
+        Ok(current)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
 #[serde(rename_all = "camelCase")]
 pub struct Charger {
-    pub id: String,
+    pub id: ChargerId,
     pub name: String,
     pub product_code: u32,
     pub color: Option<i32>,
     pub created_on: NaiveDateTime,
     pub updated_on: NaiveDateTime,
-    pub level_of_access: u32,
+    pub level_of_access: LevelOfAccess,
+}
+
+/// Access level of the user to a charger or a site
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(from = "u32", into = "u32")]
+pub enum LevelOfAccess {
+    Owner,
+    Admin,
+    User,
+    Unknown(u32),
+}
+
+impl From<u32> for LevelOfAccess {
+    fn from(level: u32) -> Self {
+        match level {
+            1 => LevelOfAccess::Owner,
+            2 => LevelOfAccess::Admin,
+            3 => LevelOfAccess::User,
+            other => LevelOfAccess::Unknown(other),
+        }
+    }
+}
+
+impl From<LevelOfAccess> for u32 {
+    fn from(level: LevelOfAccess) -> Self {
+        match level {
+            LevelOfAccess::Owner => 1,
+            LevelOfAccess::Admin => 2,
+            LevelOfAccess::User => 3,
+            LevelOfAccess::Unknown(other) => other,
+        }
+    }
+}
+
+/// Metadata of a charger. Fields left to `None` are not modified when updating.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargerUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<i32>,
+}
+
+/// Schedule of a charger: charging starts at `charge_start_time`, and, if
+/// set, stops at `charge_stop_time`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargePlan {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub charge_start_time: UtcDateTime,
+    pub charge_stop_time: Option<UtcDateTime>,
+
+    /// Repeat the plan every day
+    pub repeat: bool,
+    pub is_enabled: bool,
+
+    /// Current limit while following the plan, in A
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charging_current_limit: Option<f64>,
+}
+
+impl ChargePlan {
+    /// Delay charging until `start`
+    pub fn delayed_start(start: UtcDateTime) -> Self {
+        Self {
+            id: None,
+            charge_start_time: start,
+            charge_stop_time: None,
+            repeat: false,
+            is_enabled: true,
+            charging_current_limit: None,
+        }
+    }
+
+    /// Charge between `start` and `departure`
+    pub fn until(start: UtcDateTime, departure: UtcDateTime) -> Self {
+        Self {
+            charge_stop_time: Some(departure),
+            ..Self::delayed_start(start)
+        }
+    }
+
+    pub fn repeat_daily(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+}
+
+/// Firmware versions of a charger
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(from = "FirmwareVersions", into = "FirmwareVersions")]
+pub struct FirmwareStatus {
+    pub installed: u32,
+    pub latest: u32,
+    pub update_available: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FirmwareVersions {
+    current_version: u32,
+    latest_version: u32,
+}
+
+impl From<FirmwareVersions> for FirmwareStatus {
+    fn from(v: FirmwareVersions) -> Self {
+        Self {
+            installed: v.current_version,
+            latest: v.latest_version,
+            update_available: v.latest_version > v.current_version,
+        }
+    }
+}
+
+impl From<FirmwareStatus> for FirmwareVersions {
+    fn from(s: FirmwareStatus) -> Self {
+        Self {
+            current_version: s.installed,
+            latest_version: s.latest,
+        }
+    }
+}
+
+/// A key (RFID tag) allowed to charge on a charger
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingKey {
+    pub name: Option<String>,
+    pub token_id: String,
+    pub created_on: NaiveDateTime,
+}
+
+/// State of the LED strip of a charger, as reported in its state. Codes
+/// without a known meaning are kept as `Unknown`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(from = "u32", into = "u32")]
+pub enum LedMode {
+    Off,
+    Updating,
+    Standby,
+    SmartStandby,
+    Charging,
+    SmartCharging,
+    Paused,
+    Error,
+    Unknown(u32),
+}
+
+impl From<u32> for LedMode {
+    fn from(mode: u32) -> Self {
+        match mode {
+            0 => LedMode::Off,
+            1..=16 => LedMode::Updating,
+            17 => LedMode::Standby,
+            18 => LedMode::SmartStandby,
+            22 => LedMode::Charging,
+            23 => LedMode::Paused,
+            24 => LedMode::SmartCharging,
+            27 => LedMode::Error,
+            other => LedMode::Unknown(other),
+        }
+    }
+}
+
+/// The steps of an update are all serialized as the first one
+impl From<LedMode> for u32 {
+    fn from(mode: LedMode) -> Self {
+        match mode {
+            LedMode::Off => 0,
+            LedMode::Updating => 1,
+            LedMode::Standby => 17,
+            LedMode::SmartStandby => 18,
+            LedMode::Charging => 22,
+            LedMode::Paused => 23,
+            LedMode::SmartCharging => 24,
+            LedMode::Error => 27,
+            LedMode::Unknown(other) => other,
+        }
+    }
+}
+
+/// Configuration of a charger
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargerConfig {
+    pub is_enabled: Option<bool>,
+
+    /// Charging must be authorized with a key or the app
+    pub authorization_required: Option<bool>,
+
+    /// Charging must be started remotely, by the app or the API
+    pub remote_start_required: Option<bool>,
+    pub phase_mode: Option<PhaseMode>,
+    pub max_charger_current: Option<f64>,
+
+    /// Brightness of the LED strip, in percent
+    pub led_strip_brightness: Option<u8>,
+}
+
+/// Smart meter used for load balancing
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct Equalizer {
+    pub id: ChargerId,
+    pub name: String,
+    pub site_id: SiteId,
+    pub circuit_id: Option<CircuitId>,
+    pub created_on: NaiveDateTime,
+    pub updated_on: NaiveDateTime,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize_repr, Eq, Ord, PartialEq, PartialOrd)]
-#[repr(u8)]
+/// Current limits of a circuit, in A. Fields left to `None` are not modified when updating.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_circuit_current_p1: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_circuit_current_p2: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_circuit_current_p3: Option<f64>,
+
+    /// Limits applied when the chargers lose their connection to the cloud
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline_max_circuit_current_p1: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline_max_circuit_current_p2: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline_max_circuit_current_p3: Option<f64>,
+
+    /// Keep a small current available to idle chargers, so that cars can wake up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_idle_current: Option<bool>,
+}
+
+/// Equalizer configuration. Fields left to `None` are not modified when updating.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EqualizerConfig {
+    /// Encoded description of the electrical installation of the site
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_structure: Option<String>,
+
+    /// Maximum current the equalizer may allocate to chargers, in A
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_allocated_current: Option<f64>,
+
+    /// Type of the electricity meter the equalizer reads from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meter_type: Option<String>,
+}
+
+/// Operating mode of a charger. Codes without a known meaning, including 0
+/// for an offline charger, are kept as `Unknown`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(from = "u8", into = "u8")]
+#[non_exhaustive]
 pub enum ChargerOpMode {
-    Unknown = 0,
-    Disconnected = 1,
-    Paused = 2,
-    Charging = 3,
-    Finished = 4,
-    Error = 5,
-    Ready = 6,
-    AwaitingAuthentication = 7,
-    Deauthenticating = 8,
-}
-
-#[derive(Clone, Copy, Debug, Deserialize_repr, Eq, Ord, PartialEq, PartialOrd)]
-#[repr(u8)]
+    Disconnected,
+    Paused,
+    Charging,
+    Finished,
+    Error,
+    Ready,
+    AwaitingAuthentication,
+    Deauthenticating,
+    Unknown(u8),
+}
+
+impl From<u8> for ChargerOpMode {
+    fn from(mode: u8) -> Self {
+        match mode {
+            1 => ChargerOpMode::Disconnected,
+            2 => ChargerOpMode::Paused,
+            3 => ChargerOpMode::Charging,
+            4 => ChargerOpMode::Finished,
+            5 => ChargerOpMode::Error,
+            6 => ChargerOpMode::Ready,
+            7 => ChargerOpMode::AwaitingAuthentication,
+            8 => ChargerOpMode::Deauthenticating,
+            other => ChargerOpMode::Unknown(other),
+        }
+    }
+}
+
+impl From<ChargerOpMode> for u8 {
+    fn from(mode: ChargerOpMode) -> Self {
+        match mode {
+            ChargerOpMode::Disconnected => 1,
+            ChargerOpMode::Paused => 2,
+            ChargerOpMode::Charging => 3,
+            ChargerOpMode::Finished => 4,
+            ChargerOpMode::Error => 5,
+            ChargerOpMode::Ready => 6,
+            ChargerOpMode::AwaitingAuthentication => 7,
+            ChargerOpMode::Deauthenticating => 8,
+            ChargerOpMode::Unknown(other) => other,
+        }
+    }
+}
+
+impl std::fmt::Display for ChargerOpMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChargerOpMode::Disconnected => "Disconnected",
+            ChargerOpMode::Paused => "Paused",
+            ChargerOpMode::Charging => "Charging",
+            ChargerOpMode::Finished => "Finished",
+            ChargerOpMode::Error => "Error",
+            ChargerOpMode::Ready => "Ready",
+            ChargerOpMode::AwaitingAuthentication => "Awaiting authentication",
+            ChargerOpMode::Deauthenticating => "Deauthenticating",
+            ChargerOpMode::Unknown(other) => return write!(f, "Mode {other}"),
+        })
+    }
+}
+
+/// Phases a charger delivers current on. Codes without a known meaning are
+/// kept as `Unknown`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(from = "u8", into = "u8")]
+#[non_exhaustive]
 pub enum OutputPhase {
-    Unknown = 0,
-    L1ToN = 10,
-    L2ToN = 12,
-    L3ToN = 14,
-    L1ToL2 = 11,
-    L2ToL3 = 15,
-    L3ToL1 = 13,
-    L1L2ToN = 20,
-    L2L3ToN = 21,
-    L1L3ToL2 = 22,
-    L1L2L3ToN = 30,
+    L1ToN,
+    L2ToN,
+    L3ToN,
+    L1ToL2,
+    L2ToL3,
+    L3ToL1,
+    L1L2ToN,
+    L2L3ToN,
+    L1L3ToL2,
+    L1L2L3ToN,
+    Unknown(u8),
+}
+
+impl From<u8> for OutputPhase {
+    fn from(phase: u8) -> Self {
+        match phase {
+            10 => OutputPhase::L1ToN,
+            11 => OutputPhase::L1ToL2,
+            12 => OutputPhase::L2ToN,
+            13 => OutputPhase::L3ToL1,
+            14 => OutputPhase::L3ToN,
+            15 => OutputPhase::L2ToL3,
+            20 => OutputPhase::L1L2ToN,
+            21 => OutputPhase::L2L3ToN,
+            22 => OutputPhase::L1L3ToL2,
+            30 => OutputPhase::L1L2L3ToN,
+            other => OutputPhase::Unknown(other),
+        }
+    }
+}
+
+impl From<OutputPhase> for u8 {
+    fn from(phase: OutputPhase) -> Self {
+        match phase {
+            OutputPhase::L1ToN => 10,
+            OutputPhase::L1ToL2 => 11,
+            OutputPhase::L2ToN => 12,
+            OutputPhase::L3ToL1 => 13,
+            OutputPhase::L3ToN => 14,
+            OutputPhase::L2ToL3 => 15,
+            OutputPhase::L1L2ToN => 20,
+            OutputPhase::L2L3ToN => 21,
+            OutputPhase::L1L3ToL2 => 22,
+            OutputPhase::L1L2L3ToN => 30,
+            OutputPhase::Unknown(other) => other,
+        }
+    }
+}
+
+impl OutputPhase {
+    /// Number of phases current is delivered on, if known
+    pub fn phase_count(&self) -> Option<u8> {
+        match self {
+            OutputPhase::L1ToN
+            | OutputPhase::L2ToN
+            | OutputPhase::L3ToN
+            | OutputPhase::L1ToL2
+            | OutputPhase::L2ToL3
+            | OutputPhase::L3ToL1 => Some(1),
+            OutputPhase::L1L2ToN | OutputPhase::L2L3ToN | OutputPhase::L1L3ToL2 => Some(2),
+            OutputPhase::L1L2L3ToN => Some(3),
+            OutputPhase::Unknown(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for OutputPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OutputPhase::L1ToN => "L1-N",
+            OutputPhase::L2ToN => "L2-N",
+            OutputPhase::L3ToN => "L3-N",
+            OutputPhase::L1ToL2 => "L1-L2",
+            OutputPhase::L2ToL3 => "L2-L3",
+            OutputPhase::L3ToL1 => "L3-L1",
+            OutputPhase::L1L2ToN => "L1L2-N",
+            OutputPhase::L2L3ToN => "L2L3-N",
+            OutputPhase::L1L3ToL2 => "L1L3-L2",
+            OutputPhase::L1L2L3ToN => "L1L2L3-N",
+            OutputPhase::Unknown(other) => return write!(f, "Phase {other}"),
+        })
+    }
+}
+
+/// Phase selection setting of a charger. Codes without a known meaning are
+/// kept as `Unknown`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(from = "u8", into = "u8")]
+#[non_exhaustive]
+pub enum PhaseMode {
+    Ignore,
+    Phase1,
+    Auto,
+    Phase2,
+    Unknown(u8),
+}
+
+impl From<u8> for PhaseMode {
+    fn from(mode: u8) -> Self {
+        match mode {
+            0 => PhaseMode::Ignore,
+            1 => PhaseMode::Phase1,
+            2 => PhaseMode::Auto,
+            3 => PhaseMode::Phase2,
+            other => PhaseMode::Unknown(other),
+        }
+    }
+}
+
+impl From<PhaseMode> for u8 {
+    fn from(mode: PhaseMode) -> Self {
+        match mode {
+            PhaseMode::Ignore => 0,
+            PhaseMode::Phase1 => 1,
+            PhaseMode::Auto => 2,
+            PhaseMode::Phase2 => 3,
+            PhaseMode::Unknown(other) => other,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, PartialOrd)]
+/// Account of a user
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    /// Also identifies the user in the observations of the stream
+    pub user_id: u32,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    #[serde(rename = "phoneNo")]
+    pub phone: Option<String>,
+}
+
+/// Charger settings. Fields left to `None` are not modified when updating.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargerSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase_mode: Option<PhaseMode>,
+
+    /// Current limit of the charger, in A
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamic_charger_current: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smart_charging: Option<bool>,
+
+    /// Brightness of the LED strip, in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub led_strip_brightness: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorization_required: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_start_required: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
 #[serde(rename_all = "camelCase")]
 pub struct ChargerState {
     pub smart_charging: bool,
     pub cable_locked: bool,
     pub charger_op_mode: ChargerOpMode,
-    pub total_power: f64,
-    pub session_energy: f64,
-    pub energy_per_hour: f64,
+    pub total_power: Kilowatts,
+    pub session_energy: KilowattHours,
+    pub energy_per_hour: Kilowatts,
 
     #[serde(rename = "wiFiRSSI")]
     pub wifi_rssi: Option<i32>,
@@ -188,12 +923,15 @@ pub struct ChargerState {
     #[serde(rename = "chargerRAT")]
     pub charger_rat: u32,
     pub lock_cable_permanently: bool,
-    pub in_current_t2: Option<f64>,
-    pub in_current_t3: Option<f64>,
-    pub in_current_t4: Option<f64>,
-    pub in_current_t5: Option<f64>,
-    pub output_current: f64,
+    pub in_current_t2: Option<Amperes>,
+    pub in_current_t3: Option<Amperes>,
+    pub in_current_t4: Option<Amperes>,
+    pub in_current_t5: Option<Amperes>,
+    pub output_current: Amperes,
     pub is_online: bool,
+
+    // Not flattened into a VoltageMatrix: flattening would hide unknown
+    // fields from the schema tracking
     pub in_voltage_t1_t2: Option<f64>,
     pub in_voltage_t1_t3: Option<f64>,
     pub in_voltage_t1_t4: Option<f64>,
@@ -204,70 +942,256 @@ pub struct ChargerState {
     pub in_voltage_t3_t4: Option<f64>,
     pub in_voltage_t3_t5: Option<f64>,
     pub in_voltage_t4_t5: Option<f64>,
-    pub led_mode: u32,
-    pub cable_rating: f64,
-    pub dynamic_charger_current: f64,
-    pub circuit_total_allocated_phase_conductor_current_l1: f64,
-    pub circuit_total_allocated_phase_conductor_current_l2: f64,
-    pub circuit_total_allocated_phase_conductor_current_l3: f64,
-    pub circuit_total_phase_conductor_current_l1: f64,
-    pub circuit_total_phase_conductor_current_l2: f64,
-    pub circuit_total_phase_conductor_current_l3: f64,
-    pub reason_for_no_current: u32,
+    pub led_mode: LedMode,
+    pub cable_rating: Amperes,
+    pub dynamic_charger_current: Amperes,
+    pub circuit_total_allocated_phase_conductor_current_l1: Amperes,
+    pub circuit_total_allocated_phase_conductor_current_l2: Amperes,
+    pub circuit_total_allocated_phase_conductor_current_l3: Amperes,
+    pub circuit_total_phase_conductor_current_l1: Amperes,
+    pub circuit_total_phase_conductor_current_l2: Amperes,
+    pub circuit_total_phase_conductor_current_l3: Amperes,
+    pub reason_for_no_current: ReasonForNoCurrent,
 
     #[serde(rename = "wiFiAPEnabled")]
     pub wifi_ap_enabled: bool,
-    pub lifetime_energy: f64,
+    pub lifetime_energy: KilowattHours,
     pub offline_max_circuit_current_p1: u32,
     pub offline_max_circuit_current_p2: u32,
     pub offline_max_circuit_current_p3: u32,
     pub error_code: u32,
     pub fatal_error_code: u32,
-    pub eq_available_current_p1: Option<f64>,
-    pub eq_available_current_p2: Option<f64>,
-    pub eq_available_current_p3: Option<f64>,
-    pub derated_current: Option<f64>,
+    pub eq_available_current_p1: Option<Amperes>,
+    pub eq_available_current_p2: Option<Amperes>,
+    pub eq_available_current_p3: Option<Amperes>,
+    pub derated_current: Option<Amperes>,
     pub derating_active: bool,
     pub connected_to_cloud: bool,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, PartialOrd)]
-#[serde(rename_all = "camelCase")]
-pub struct ChargingSession {
-    pub charger_id: Option<String>,
-    pub session_energy: f64,
-    //pub session_start: Option<NaiveDateTime>,
-    //pub session_stop: Option<NaiveDateTime>,
-    pub session_id: Option<i32>,
-    pub charge_duration_in_seconds: Option<u32>,
-    //pub first_energy_transfer_period_start: Option<NaiveDateTime>,
-    //pub last_energy_transfer_period_end: Option<NaiveDateTime>,
-    #[serde(rename = "pricePrKwhIncludingVat")]
-    pub price_per_kwh_including_vat: Option<f64>,
-    pub price_per_kwh_excluding_vat: Option<f64>,
-    pub vat_percentage: Option<f64>,
-    pub currency_id: Option<String>,
-    pub cost_including_vat: Option<f64>,
-    pub cost_excluding_vat: Option<f64>,
-}
+impl ChargerState {
+    /// Fields changed from `self` to `other`, to publish deltas rather than
+    /// whole snapshots
+    pub fn diff(&self, other: &ChargerState) -> StateDiff {
+        StateDiff::between(self, other)
+    }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Address {}
+    pub fn is_charging(&self) -> bool {
+        self.charger_op_mode == ChargerOpMode::Charging
+    }
 
-#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
-#[serde(rename_all = "camelCase")]
-pub struct Site {
-    pub uuid: Option<String>,
-    pub id: u32,
+    /// Number of phases the charger delivers current on, or 0 if unknown
+    pub fn active_phase_count(&self) -> u8 {
+        self.output_phase.phase_count().unwrap_or(0)
+    }
+
+    /// Output current summed over the active phases
+    pub fn total_current(&self) -> Amperes {
+        self.output_current * f64::from(self.active_phase_count())
+    }
+
+    /// Power computed from the output current and voltage, to cross-check
+    /// `total_power`, which some firmwares update late
+    pub fn estimated_power(&self) -> Kilowatts {
+        Kilowatts(self.voltage * self.total_current().0 / 1000.0)
+    }
+
+    /// Signal strength of the link to the cloud, in dBm, of the radio
+    /// selected by `charger_rat`: Wi-Fi (1) or cellular (2). `None` if the
+    /// charger reports no link, or no strength for it.
+    pub fn signal_strength(&self) -> Option<i32> {
+        match self.charger_rat {
+            1 => self.wifi_rssi,
+            2 => self.cell_rssi,
+            _ => None,
+        }
+    }
+
+    /// Voltages measured between each pair of input pins
+    pub fn in_voltage(&self) -> VoltageMatrix {
+        VoltageMatrix {
+            in_voltage_t1_t2: self.in_voltage_t1_t2,
+            in_voltage_t1_t3: self.in_voltage_t1_t3,
+            in_voltage_t1_t4: self.in_voltage_t1_t4,
+            in_voltage_t1_t5: self.in_voltage_t1_t5,
+            in_voltage_t2_t3: self.in_voltage_t2_t3,
+            in_voltage_t2_t4: self.in_voltage_t2_t4,
+            in_voltage_t2_t5: self.in_voltage_t2_t5,
+            in_voltage_t3_t4: self.in_voltage_t3_t4,
+            in_voltage_t3_t5: self.in_voltage_t3_t5,
+            in_voltage_t4_t5: self.in_voltage_t4_t5,
+        }
+    }
+
+    /// Current drawn by the whole circuit of the charger
+    pub fn circuit_current(&self) -> Triphase {
+        Triphase::new(
+            self.circuit_total_phase_conductor_current_l1,
+            self.circuit_total_phase_conductor_current_l2,
+            self.circuit_total_phase_conductor_current_l3,
+        )
+    }
+
+    /// Current allocated by load balancing to the whole circuit of the charger
+    pub fn allocated_circuit_current(&self) -> Triphase {
+        Triphase::new(
+            self.circuit_total_allocated_phase_conductor_current_l1,
+            self.circuit_total_allocated_phase_conductor_current_l2,
+            self.circuit_total_allocated_phase_conductor_current_l3,
+        )
+    }
+}
+
+/// One-line summary, such as `Charging on L1L2L3-N: 11.0 kW, 16.0 A, session 4.25 kWh`
+impl std::fmt::Display for ChargerState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on {}: {:.1}, {:.1}, session {:.2}",
+            self.charger_op_mode,
+            self.output_phase,
+            self.total_power,
+            self.output_current,
+            self.session_energy
+        )?;
+        if !self.is_online {
+            f.write_str(" (offline)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingSession {
+    pub charger_id: Option<ChargerId>,
+    pub session_energy: KilowattHours,
+    #[serde(default, deserialize_with = "deserialize_optional_utc")]
+    pub session_start: Option<UtcDateTime>,
+    #[serde(default, deserialize_with = "deserialize_optional_utc")]
+    pub session_stop: Option<UtcDateTime>,
+    pub session_id: Option<i32>,
+    pub charge_duration_in_seconds: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_optional_utc")]
+    pub first_energy_transfer_period_start: Option<UtcDateTime>,
+    #[serde(default, deserialize_with = "deserialize_optional_utc")]
+    pub last_energy_transfer_period_end: Option<UtcDateTime>,
+    #[serde(rename = "pricePrKwhIncludingVat")]
+    pub price_per_kwh_including_vat: Option<f64>,
+    pub price_per_kwh_excluding_vat: Option<f64>,
+    pub vat_percentage: Option<f64>,
+    pub currency_id: Option<String>,
+    pub cost_including_vat: Option<f64>,
+    pub cost_excluding_vat: Option<f64>,
+}
+
+impl ChargingSession {
+    /// Cost of the session including VAT, if a price is configured
+    pub fn cost(&self) -> Option<Money> {
+        Some(Money::new(
+            self.cost_including_vat?,
+            self.currency_id.as_deref()?,
+        ))
+    }
+}
+
+/// One-line summary, such as `EH123456: 4.25 kWh from 2024-05-01 10:00:00 UTC, 2.35 NOK`
+impl std::fmt::Display for ChargingSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(charger) = &self.charger_id {
+            write!(f, "{charger}: ")?;
+        }
+        write!(f, "{:.2}", self.session_energy)?;
+        if let Some(start) = self.session_start {
+            write!(f, " from {}", start.0)?;
+        }
+        if let Some(stop) = self.session_stop {
+            write!(f, " to {}", stop.0)?;
+        }
+        if let Some(cost) = self.cost() {
+            write!(f, ", {cost}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Coordinate of a location. Totally ordered, as by [`f64::total_cmp`], so
+/// that sites and their addresses can be sorted and compared.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Coordinate(pub f64);
+
+impl PartialEq for Coordinate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Coordinate {}
+
+impl PartialOrd for Coordinate {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Coordinate {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Postal address and location of a site
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct Address {
+    pub street: Option<String>,
+    pub building_number: Option<String>,
+    pub zip: Option<String>,
+    #[serde(rename = "area")]
+    pub city: Option<String>,
+    pub country: Option<Country>,
+
+    /// In degrees
+    pub latitude: Option<Coordinate>,
+
+    /// In degrees
+    pub longitude: Option<Coordinate>,
+
+    /// In meters
+    pub altitude: Option<Coordinate>,
+}
+
+impl Address {
+    /// Latitude and longitude, in degrees
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        Some((self.latitude?.0, self.longitude?.0))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct Country {
+    /// ISO 3166 code, such as `NO`
+    pub id: String,
+    pub name: Option<String>,
+    pub phone_prefix: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct Site {
+    pub uuid: Option<String>,
+    pub id: SiteId,
     pub site_key: Option<String>,
     pub name: Option<String>,
-    pub level_of_access: u32,
-    //pub address: Address,
+    pub level_of_access: LevelOfAccess,
+    #[serde(default)]
+    pub address: Option<Address>,
     pub installer_alias: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SiteDetails {
     #[serde(flatten)]
@@ -275,12 +1199,79 @@ pub struct SiteDetails {
     pub circuits: Vec<Circuit>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// Chargers and equalizers of a site, as listed by [`Context::products`]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteProducts {
+    #[serde(flatten)]
+    pub site: Site,
+    #[serde(default)]
+    pub circuits: Vec<CircuitProducts>,
+    #[serde(default)]
+    pub equalizers: Vec<Equalizer>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitProducts {
+    pub id: CircuitId,
+    #[serde(default)]
+    pub chargers: Vec<Charger>,
+}
+
+/// Pricing and contact settings of a site
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteSettings {
+    /// Price of energy, including VAT
+    #[serde(rename = "costPerKWh")]
+    pub cost_per_kwh: Option<f64>,
+
+    /// Price of energy, excluding VAT
+    #[serde(rename = "costPerKwhExcludeVat")]
+    pub cost_per_kwh_excluding_vat: Option<f64>,
+
+    /// VAT rate, in percent
+    pub vat: Option<f64>,
+    pub currency_id: Option<String>,
+    pub contact_info: Option<ContactInfo>,
+}
+
+impl SiteSettings {
+    /// Price of energy including VAT, per kWh, if configured
+    pub fn price_per_kwh(&self) -> Option<Money> {
+        Some(Money::new(self.cost_per_kwh?, self.currency_id.as_deref()?))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactInfo {
+    pub installer_name: Option<String>,
+    pub installer_phone_number: Option<String>,
+    pub installer_email: Option<String>,
+}
+
+/// A user with access to a site
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteUser {
+    pub user_id: u32,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub role: LevelOfAccess,
+
+    /// IDs of the chargers the user may use
+    #[serde(default)]
+    pub chargers: Vec<ChargerId>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Circuit {
-    pub id: u32,
+    pub id: CircuitId,
     pub uuid: String,
-    pub site_id: u32,
+    pub site_id: SiteId,
     pub circuit_panel_id: i64,
     pub panel_name: String,
     pub rated_current: f64,
@@ -292,20 +1283,21 @@ pub struct Circuit {
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginResponse {
-    pub access_token: String,
+    pub access_token: SecretString,
     pub expires_in: u32,
     pub access_claims: Vec<Option<String>>,
     pub token_type: Option<String>,
-    pub refresh_token: String,
+    pub refresh_token: SecretString,
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
+/// Acknowledgement of a command sent to a device. Some commands are accepted
+/// without one.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
-struct CommandReply {
-    command_id: u64,
-    device: String,
-    ticks: u64,
+pub struct CommandReply {
+    pub command_id: u64,
+    pub device: String,
+    pub ticks: u64,
 }
 
 #[derive(Debug, Error)]
@@ -314,10 +1306,25 @@ pub enum ApiError {
     #[error("io: {0}")]
     IO(#[from] io::Error),
 
-    /// HTTP call failed (404, etc)
+    /// HTTP call failed before a response was received
     #[error("ureq")]
     Ureq(#[source] Box<ureq::Error>),
 
+    /// The server answered with an error status (400, 404, etc). Easee
+    /// usually describes the error in a JSON body, which `code` and `title`
+    /// are extracted from.
+    #[error("server error {status}: {}", title.as_deref().unwrap_or("no details"))]
+    Server {
+        status: u16,
+        code: Option<i32>,
+        code_name: Option<String>,
+        title: Option<String>,
+        body: String,
+
+        /// Delay requested by the `Retry-After` header, on 429 and 503
+        retry_after: Option<Duration>,
+    },
+
     /// HTTP call succeeded but the returned JSON document didn't match the expected format
     #[error("unexpected data: {1} when processing {0}")]
     UnexpectedData(serde_json::Value, serde_json::Error),
@@ -332,11 +1339,135 @@ pub enum ApiError {
 
     #[error("Invalid ID: {0:?}")]
     InvalidID(String),
+
+    /// A current to set is negative, or above the rated current of the circuit
+    #[error("invalid current {current}, must be between 0 and {rated}")]
+    InvalidCurrent { current: Amperes, rated: Amperes },
+
+    /// An observation value could not be decoded
+    #[error("observation: {0}")]
+    Observation(#[from] ParseError),
+}
+
+/// HTTP statuses meaning that a resource is absent, rather than an error.
+/// By default, 404 Not Found and 204 No Content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MissingPolicy {
+    pub not_found: bool,
+    pub no_content: bool,
+    pub forbidden: bool,
+}
+
+impl Default for MissingPolicy {
+    fn default() -> Self {
+        Self {
+            not_found: true,
+            no_content: true,
+            forbidden: false,
+        }
+    }
+}
+
+impl MissingPolicy {
+    /// Also treat 403 Forbidden as absent, such as for chargers of shared
+    /// sites the user can't access
+    pub fn forbidden(mut self, forbidden: bool) -> Self {
+        self.forbidden = forbidden;
+        self
+    }
+
+    fn is_missing(&self, status: u16) -> bool {
+        match status {
+            404 => self.not_found,
+            204 => self.no_content,
+            403 => self.forbidden,
+            _ => false,
+        }
+    }
+}
+
+impl ApiError {
+    /// Stable identifier of the kind of error, for alert rules and
+    /// documentation. Codes never change once assigned, unlike messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::IO(_) => "api.io",
+            ApiError::Ureq(e) => match **e {
+                ureq::Error::Status(..) => "api.http_status",
+                ureq::Error::Transport(_) => "api.transport",
+            },
+            ApiError::Server { .. } => "api.http_status",
+            ApiError::UnexpectedData(..) => "api.unexpected_data",
+            ApiError::DeserializeFail => "api.datetime_type",
+            ApiError::FormatError(_) => "api.datetime_format",
+            ApiError::InvalidID(_) => "api.invalid_id",
+            ApiError::InvalidCurrent { .. } => "api.invalid_current",
+            ApiError::Observation(_) => "api.observation",
+        }
+    }
+
+    /// HTTP status returned by the server, if the error comes from it
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ApiError::Server { status, .. } => Some(*status),
+            ApiError::Ureq(e) => match **e {
+                ureq::Error::Status(status, _) => Some(status),
+                ureq::Error::Transport(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Hide tokens from the JSON document embedded in the error, if any
+    fn redacted(self) -> Self {
+        match self {
+            ApiError::UnexpectedData(value, e) => ApiError::UnexpectedData(redact(value), e),
+            other => other,
+        }
+    }
+}
+
+/// Error document returned by the API along with error statuses
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerErrorBody {
+    error_code: Option<i32>,
+    error_code_name: Option<String>,
+    title: Option<String>,
+}
+
+/// Parse a `Retry-After` header, either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
 }
 
 impl From<ureq::Error> for ApiError {
     fn from(value: ureq::Error) -> Self {
-        ApiError::Ureq(Box::new(value))
+        match value {
+            ureq::Error::Status(status, resp) => {
+                let retry_after = resp.header("Retry-After").and_then(parse_retry_after);
+                let body = resp.into_string().unwrap_or_default();
+                let details: Option<ServerErrorBody> = serde_json::from_str(&body).ok();
+                let (code, code_name, title) = details
+                    .map(|d| (d.error_code, d.error_code_name, d.title))
+                    .unwrap_or_default();
+                ApiError::Server {
+                    status,
+                    code,
+                    code_name,
+                    title,
+                    body,
+                    retry_after,
+                }
+            }
+            transport => ApiError::Ureq(Box::new(transport)),
+        }
     }
 }
 
@@ -360,57 +1491,330 @@ pub enum TokenParseError {
 
     #[error("Parse error: {0}")]
     ParseIntError(#[from] std::num::ParseIntError),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Malformed JWT")]
+    MalformedJwt,
+
+    #[error("Invalid expiration date: {0}")]
+    InvalidExpiration(f64),
+}
+
+/// Tokens of a [`Context`] at some point in time, such as after a refresh
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenSnapshot {
+    pub access_token: SecretString,
+    pub refresh_token: SecretString,
+    pub expires_at: UtcDateTime,
+}
+
+/// Login credentials of an account
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub user: String,
+    pub password: SecretString,
+}
+
+/// Token cache in the format used by pyeasee: the login response, with the
+/// expiration date added as a Unix timestamp
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PyeaseeToken {
+    access_token: String,
+    refresh_token: String,
+    #[serde(default)]
+    expires_in: u32,
+    #[serde(default)]
+    token_type: Option<String>,
+    #[serde(default)]
+    access_claims: Vec<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_expires")]
+    expires: Option<f64>,
+}
+
+/// pyeasee versions differ on the expiration format: Unix timestamp or ISO date
+fn deserialize_expires<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    use serde::de::Error;
+    Ok(match Option::<serde_json::Value>::deserialize(d)? {
+        Some(serde_json::Value::Number(n)) => n.as_f64(),
+        Some(serde_json::Value::String(s)) => {
+            let dt = chrono::DateTime::parse_from_rfc3339(&s)
+                .map(|dt| dt.to_utc())
+                .or_else(|_| s.parse::<chrono::NaiveDateTime>().map(|dt| dt.and_utc()))
+                .map_err(D::Error::custom)?;
+            Some(dt.timestamp() as f64)
+        }
+        _ => None,
+    })
+}
+
+/// Tokens are never issued for longer than this
+const MAX_TOKEN_LIFETIME: Duration = Duration::from_secs(2 * 86400);
+
+/// Allowed drift between the clock that saved a token and the current one
+const CLOCK_TOLERANCE: Duration = Duration::from_secs(300);
+
+/// Check that saved token dates make sense with respect to the current time.
+/// Devices without a real-time clock may boot with a clock far in the past,
+/// or jump forward once synchronized.
+fn plausible_token_dates(issued: Option<SystemTime>, expire: SystemTime, now: SystemTime) -> bool {
+    if expire > now + MAX_TOKEN_LIFETIME {
+        return false;
+    }
+    let Some(issued) = issued else {
+        return true;
+    };
+    issued <= now + CLOCK_TOLERANCE
+        && issued <= expire
+        && expire.duration_since(issued).unwrap_or_default() <= MAX_TOKEN_LIFETIME
 }
 
 impl Context {
-    fn from_login_response(resp: LoginResponse) -> Self {
+    pub(crate) fn new(
+        access_token: &str,
+        refresh_token: SecretString,
+        token_expiration: Instant,
+    ) -> Self {
+        let config = ClientConfig::default();
         Self {
-            auth_header: format!("Bearer {}", &resp.access_token),
-            refresh_token: resp.refresh_token,
-            token_expiration: (Instant::now() + Duration::from_secs(resp.expires_in as u64)),
+            agent: config.agent(),
+            config,
+            auth_header: format!("Bearer {}", access_token).into(),
+            refresh_token,
+            token_expiration,
+            token_issued: SystemTime::now(),
+            rate_budget: None,
+            rate_limiter: None,
+            retry: None,
+            etags: None,
+            ttl_cache: None,
+            topology: None,
             on_refresh: None,
+            token_store: None,
+            credentials: None,
+            paused_circuits: HashMap::new(),
+            on_pause: None,
+            schema: SchemaTracker::default(),
+            shared: None,
+            #[cfg(feature = "debug-bodies")]
+            body_capture: None,
         }
     }
 
+    fn from_login_response(resp: LoginResponse) -> Self {
+        Self::new(
+            resp.access_token.expose(),
+            resp.refresh_token,
+            Instant::now() + Duration::from_secs(resp.expires_in as u64),
+        )
+    }
+
+    /// Restore tokens saved with [`Context::save`]. If the saved dates are
+    /// inconsistent with the current clock, the access token is considered
+    /// expired and will be refreshed on first use.
     pub fn from_saved(saved: &str) -> Result<Self, TokenParseError> {
         let lines: Vec<&str> = saved.lines().collect();
-        let &[token, refresh, expire] = &*lines else {
-            return Err(TokenParseError::IncorrectLineCount);
+        let (token, refresh, expire, issued) = match *lines {
+            [token, refresh, expire] => (token, refresh, expire, None),
+            [token, refresh, expire, issued] => (token, refresh, expire, Some(issued.parse()?)),
+            _ => return Err(TokenParseError::IncorrectLineCount),
         };
 
-        let expire: u64 = expire.parse()?;
-        let token_expiration = Instant::now()
-            + (UNIX_EPOCH + Duration::from_secs(expire))
-                .duration_since(SystemTime::now())
-                .unwrap_or_default();
+        let expire = UNIX_EPOCH + Duration::from_secs(expire.parse()?);
+        let issued = issued.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        let now = SystemTime::now();
 
-        Ok(Self {
-            auth_header: format!("Bearer {}", token),
-            refresh_token: refresh.to_owned(),
+        let token_expiration = if plausible_token_dates(issued, expire, now) {
+            Instant::now() + expire.duration_since(now).unwrap_or_default()
+        } else {
+            warn!("Saved token dates are inconsistent with the system clock, forcing refresh");
+            Instant::now()
+        };
+
+        let mut ctx = Self::new(token, refresh.into(), token_expiration);
+        if let Some(issued) = issued {
+            ctx.token_issued = issued;
+        }
+        Ok(ctx)
+    }
+
+    /// Restore tokens from a pyeasee token cache. To share a refresh token
+    /// with pyeasee, write the file back with [`Context::save_pyeasee`] every
+    /// time the tokens are refreshed.
+    pub fn from_pyeasee(saved: &str) -> Result<Self, TokenParseError> {
+        let saved: PyeaseeToken = serde_json::from_str(saved)?;
+        let now = SystemTime::now();
+        let expire = saved
+            .expires
+            .map(|secs| {
+                Duration::try_from_secs_f64(secs.max(0.0))
+                    .ok()
+                    .and_then(|d| UNIX_EPOCH.checked_add(d))
+                    .ok_or(TokenParseError::InvalidExpiration(secs))
+            })
+            .transpose()?;
+
+        let token_expiration = match expire {
+            Some(expire) if plausible_token_dates(None, expire, now) => {
+                Instant::now() + expire.duration_since(now).unwrap_or_default()
+            }
+            _ => {
+                warn!("No usable expiration date in pyeasee token, forcing refresh");
+                Instant::now()
+            }
+        };
+
+        Ok(Self::new(
+            &saved.access_token,
+            saved.refresh_token.into(),
             token_expiration,
-            on_refresh: None,
-        })
+        ))
     }
 
-    pub fn on_refresh<F: FnMut(&mut Self) + Send + 'static>(mut self, on_refresh: F) -> Self {
+    /// The current tokens
+    pub fn snapshot(&self) -> TokenSnapshot {
+        let expires_at = chrono::Utc::now()
+            + chrono::Duration::from_std(self.token_expires_in()).unwrap_or_default();
+        TokenSnapshot {
+            access_token: self.auth_token().into(),
+            refresh_token: self.refresh_token.clone(),
+            expires_at: UtcDateTime(expires_at),
+        }
+    }
+
+    /// Restore tokens taken with [`Context::snapshot`]
+    pub fn from_snapshot(snapshot: &TokenSnapshot) -> Self {
+        let remaining = (snapshot.expires_at.0 - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        Self::new(
+            snapshot.access_token.expose(),
+            snapshot.refresh_token.clone(),
+            Instant::now() + remaining,
+        )
+    }
+
+    /// Save the tokens in the pyeasee token cache format
+    pub fn save_pyeasee(&self) -> SecretString {
+        let remaining = self
+            .token_expiration
+            .saturating_duration_since(Instant::now());
+        let expires = (SystemTime::now() + remaining)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let token = PyeaseeToken {
+            access_token: self.auth_token().to_owned(),
+            refresh_token: self.refresh_token.expose().to_owned(),
+            expires_in: remaining.as_secs() as u32,
+            token_type: Some("Bearer".to_owned()),
+            access_claims: vec![],
+            expires: Some(expires.as_secs_f64()),
+        };
+        serde_json::to_string(&token)
+            .expect("token serialization cannot fail")
+            .into()
+    }
+
+    /// Restore the tokens kept in `store`, and keep it up to date on every
+    /// refresh. Returns `None` if the store is empty, in which case log in and
+    /// attach the store with [`Context::token_store`].
+    pub fn from_store<T: TokenStore + 'static>(
+        mut store: T,
+    ) -> Result<Option<Self>, TokenStoreError> {
+        let Some(saved) = store.load()? else {
+            return Ok(None);
+        };
+        let mut ctx = Self::from_pyeasee(saved.expose())?;
+        ctx.token_store = Some(Box::new(store));
+        Ok(Some(ctx))
+    }
+
+    /// Save the tokens into `store` now, and on every refresh
+    pub fn token_store<T: TokenStore + 'static>(
+        mut self,
+        mut store: T,
+    ) -> Result<Self, TokenStoreError> {
+        store.save(&self.save_pyeasee())?;
+        self.token_store = Some(Box::new(store));
+        Ok(self)
+    }
+
+    /// Register a callback receiving the new tokens after every refresh
+    pub fn on_refresh<F: FnMut(&TokenSnapshot) + Send + 'static>(mut self, on_refresh: F) -> Self {
         self.on_refresh = Some(Box::new(on_refresh));
         self
     }
 
-    pub fn save(&self) -> String {
+    /// Register a callback notified when a circuit is paused, with the current
+    /// to restore, and when it is restored, with `None`. Use it to persist the
+    /// paused currents, and [`Context::set_paused_current`] to reload them.
+    pub fn on_circuit_pause<F: FnMut(CircuitId, Option<&Triphase>) + Send + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.on_pause = Some(Box::new(callback));
+        self
+    }
+
+    /// Record the current to restore on a paused circuit, as persisted
+    /// before a restart
+    pub fn set_paused_current(&mut self, circuit_id: CircuitId, current: Triphase) {
+        self.paused_circuits.insert(circuit_id, current);
+    }
+
+    /// Current to restore on a circuit, if it was paused
+    pub fn paused_current(&self, circuit_id: CircuitId) -> Option<Triphase> {
+        self.paused_circuits.get(&circuit_id).copied()
+    }
+
+    fn notify_pause(&mut self, circuit_id: CircuitId) {
+        let current = self.paused_circuits.get(&circuit_id);
+        if let Some(cb) = &mut self.on_pause {
+            cb(circuit_id, current);
+        }
+    }
+
+    pub fn save(&self) -> SecretString {
         let expiration = (SystemTime::now() + (self.token_expiration - Instant::now()))
             .duration_since(UNIX_EPOCH)
             .unwrap();
+        let issued = self
+            .token_issued
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
         format!(
-            "{}\n{}\n{}\n",
+            "{}\n{}\n{}\n{}\n",
             self.auth_token(),
-            self.refresh_token,
-            expiration.as_secs()
+            self.refresh_token.expose(),
+            expiration.as_secs(),
+            issued.as_secs()
         )
+        .into()
     }
 
     /// Retrieve access tokens online, by logging in with the provided credentials
     pub fn from_login(user: &str, password: &str) -> Result<Self, ApiError> {
+        Self::from_login_with(ClientConfig::default(), user, password)
+    }
+
+    /// Log in as with [`Context::from_login`], on the hosts of `config`
+    pub fn from_login_with(
+        config: ClientConfig,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, ApiError> {
+        let resp = Self::login(&config, &config.agent(), user, password)?;
+        Ok(Self::from_login_response(resp).config(config))
+    }
+
+    fn login(
+        config: &ClientConfig,
+        agent: &ureq::Agent,
+        user: &str,
+        password: &str,
+    ) -> Result<LoginResponse, ApiError> {
         #[derive(Serialize)]
         #[serde(rename_all = "camelCase")]
         struct Params<'t> {
@@ -419,15 +1823,57 @@ impl Context {
         }
 
         info!("Logging into API");
-        let url: String = format!("{}accounts/login", API_BASE);
-        let resp: LoginResponse = ureq::post(&url)
+        let url = config.url("accounts/login");
+        agent
+            .post(&url)
             .send_json(Params {
                 user_name: user,
                 password,
             })?
-            .into_json_with_error()?;
+            .into_json_with_error()
+            .map_err(ApiError::redacted)
+    }
+
+    /// Keep credentials to log in again when the refresh token is rejected,
+    /// such as after a long downtime
+    pub fn credentials(self, user: &str, password: SecretString) -> Self {
+        let credentials = Credentials {
+            user: user.to_owned(),
+            password,
+        };
+        self.credentials_provider(move || Some(credentials.clone()))
+    }
+
+    /// Like [`Context::credentials`], with credentials fetched only when
+    /// needed, such as from a vault. Returning `None` gives up logging in.
+    pub fn credentials_provider<F>(mut self, provider: F) -> Self
+    where
+        F: FnMut() -> Option<Credentials> + Send + 'static,
+    {
+        self.credentials = Some(Box::new(provider));
+        self
+    }
+
+    pub(crate) fn charger_command(
+        &mut self,
+        charger_id: &ChargerId,
+        command: &str,
+    ) -> Result<Option<CommandReply>, ApiError> {
+        self.post(
+            &format!("chargers/{}/commands/{}", charger_id, command),
+            &(),
+        )
+    }
+
+    /// Decode the claims of the access token, such as the user ID
+    pub fn token_claims(&self) -> Result<TokenClaims, TokenParseError> {
+        claims::decode(self.auth_token())
+    }
 
-        Ok(Self::from_login_response(resp))
+    /// Time left before the access token expires
+    pub fn token_expires_in(&self) -> Duration {
+        self.token_expiration
+            .saturating_duration_since(Instant::now())
     }
 
     /// Check if the token has reached its expiration date
@@ -439,61 +1885,297 @@ impl Context {
         Ok(())
     }
 
-    pub(crate) fn auth_token(&self) -> &str {
-        &self.auth_header[7..]
+    /// A bare context using the same access token and rate budget, for use on
+    /// another thread. It has no refresh token, so that it can never rotate
+    /// the tokens of this context behind its back.
+    pub(crate) fn fork(&mut self) -> Result<Context, ApiError> {
+        self.fork_shared(None)
     }
 
-    /// Use the refresh token to refresh credentials
-    pub fn refresh_token(&mut self) -> Result<(), ApiError> {
-        #[derive(Serialize)]
-        #[serde(rename_all = "camelCase")]
-        struct Params<'t> {
-            refresh_token: &'t str,
-        }
+    /// Fork a context refreshing its tokens through `shared`, which must be
+    /// the mutex holding `self`
+    pub(crate) fn fork_shared(
+        &mut self,
+        shared: Option<Arc<Mutex<Context>>>,
+    ) -> Result<Context, ApiError> {
+        self.check_expired()?;
+        Ok(Self {
+            config: self.config.clone(),
+            agent: self.agent.clone(),
+            auth_header: self.auth_header.clone(),
+            refresh_token: SecretString::default(),
+            token_expiration: self.token_expiration,
+            token_issued: self.token_issued,
+            rate_budget: self.rate_budget.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            retry: self.retry,
+            etags: None,
+            ttl_cache: None,
+            topology: None,
+            on_refresh: None,
+            token_store: None,
+            credentials: None,
+            paused_circuits: HashMap::new(),
+            on_pause: None,
+            schema: SchemaTracker::default(),
+            shared,
+            #[cfg(feature = "debug-bodies")]
+            body_capture: None,
+        })
+    }
 
-        info!("Refreshing access token");
-        let params = Params {
-            refresh_token: &self.refresh_token,
-        };
-        let url = format!("{}accounts/refresh_token", API_BASE);
-        let resp: LoginResponse = ureq::post(&url)
-            .set("Content-type", "application/json")
-            .send_json(params)?
-            .into_json_with_error()?;
+    /// Use the hosts and timeouts of `config`, such as after restoring saved tokens
+    pub fn config(mut self, config: ClientConfig) -> Self {
+        self.agent = config.agent();
+        self.config = config;
+        self
+    }
 
-        *self = Self::from_login_response(resp);
-        Ok(())
+    #[cfg(feature = "tungstenite")]
+    pub(crate) fn client_config(&self) -> &ClientConfig {
+        &self.config
     }
 
-    /// List all sites available to the user
-    pub fn sites(&mut self) -> Result<Vec<Site>, ApiError> {
-        self.get("sites")
+    /// Share a request rate budget with the other processes using the same
+    /// budget file. Every API call waits for a token from the budget.
+    pub fn shared_rate_budget(mut self, budget: SharedRateBudget) -> Self {
+        self.rate_budget = Some(budget);
+        self
     }
 
-    pub fn site(&mut self, id: i32) -> Result<SiteDetails, ApiError> {
-        self.get(&format!("sites/{id}"))
+    /// Retry GET requests failing with transient errors, such as 502 Bad Gateway.
+    /// Other methods are never retried, as they may not be idempotent.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
     }
 
-    /// List all chargers available to the user
-    pub fn chargers(&mut self) -> Result<Vec<Charger>, ApiError> {
-        self.get("chargers")
+    /// Limit the request rate of this context, and of its clones sharing the
+    /// same limiter
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
     }
 
-    pub fn charger(&mut self, id: &str) -> Result<Charger, ApiError> {
-        if !id.chars().all(char::is_alphanumeric) {
-            return Err(ApiError::InvalidID(id.to_owned()));
-        }
-        self.get(&format!("chargers/{}", id))
+    /// Revalidate the responses of the last `capacity` GET requests with their
+    /// `ETag`, so that unchanged documents are not downloaded again
+    pub fn etag_cache(mut self, capacity: usize) -> Self {
+        self.etags = Some(EtagCache::new(capacity));
+        self
     }
 
-    pub fn circuit(&mut self, site_id: u32, circuit_id: u32) -> Result<Circuit, ApiError> {
-        self.get(&format!("site/{site_id}/circuit/{circuit_id}"))
+    pub(crate) fn ttl_cache(&mut self) -> &mut TtlCache {
+        self.ttl_cache.get_or_insert_with(TtlCache::default)
     }
 
-    pub fn circuit_dynamic_current(
-        &mut self,
-        site_id: u32,
-        circuit_id: u32,
+    pub(crate) fn disable_ttl_cache(&mut self) {
+        self.ttl_cache = None;
+    }
+
+    /// Response of a GET still fresh in the TTL cache
+    fn cached<T: DeserializeOwned>(&mut self, path: &str) -> Option<Result<T, ApiError>> {
+        let value = self.ttl_cache.as_ref()?.get(path, Instant::now())?;
+        Some(
+            self.schema
+                .deserialize(path, &value)
+                .map_err(|e| ApiError::UnexpectedData(value, e)),
+        )
+    }
+
+    /// Wait until the rate limits allow a request
+    fn throttle(&mut self) -> Result<(), ApiError> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire();
+        }
+        if let Some(budget) = &self.rate_budget {
+            budget.acquire()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn auth_token(&self) -> &str {
+        &self.auth_header.expose()[7..]
+    }
+
+    /// Use the refresh token to refresh credentials. Contexts of a
+    /// [`crate::shared::SharedContext`] take the tokens of the shared context
+    /// instead, refreshing them first unless another context already did.
+    pub fn refresh_token(&mut self) -> Result<(), ApiError> {
+        if let Some(shared) = self.shared.clone() {
+            let mut shared = shared.lock().unwrap_or_else(|e| e.into_inner());
+            if shared.token_expiration <= self.token_expiration {
+                shared.refresh_token()?;
+            }
+            self.auth_header = shared.auth_header.clone();
+            self.token_expiration = shared.token_expiration;
+            self.token_issued = shared.token_issued;
+            return Ok(());
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params<'t> {
+            refresh_token: &'t str,
+        }
+
+        info!("Refreshing access token");
+        let params = Params {
+            refresh_token: self.refresh_token.expose(),
+        };
+        let url = self.config.url("accounts/refresh_token");
+        let refreshed = self
+            .agent
+            .post(&url)
+            .set("Content-type", "application/json")
+            .send_json(params)
+            .map_err(ApiError::from)
+            .and_then(|resp| resp.into_json_with_error().map_err(ApiError::redacted));
+
+        let resp = match refreshed {
+            Err(e) if matches!(e.status(), Some(400 | 401)) => match self.relogin()? {
+                Some(resp) => resp,
+                None => return Err(e),
+            },
+            other => other?,
+        };
+
+        let fresh = Self::from_login_response(resp);
+        self.auth_header = fresh.auth_header;
+        self.refresh_token = fresh.refresh_token;
+        self.token_expiration = fresh.token_expiration;
+        self.token_issued = fresh.token_issued;
+
+        let saved = self.save_pyeasee();
+        if let Some(store) = &mut self.token_store {
+            if let Err(e) = store.save(&saved) {
+                warn!("Could not persist refreshed tokens: {e}");
+            }
+        }
+        let snapshot = self.snapshot();
+        if let Some(cb) = &mut self.on_refresh {
+            cb(&snapshot);
+        }
+        Ok(())
+    }
+
+    /// Log in again with the stored credentials, if any
+    fn relogin(&mut self) -> Result<Option<LoginResponse>, ApiError> {
+        let Some(credentials) = self.credentials.as_mut().and_then(|provide| provide()) else {
+            return Ok(None);
+        };
+        warn!("Refresh token rejected, logging in again");
+        let resp = Self::login(
+            &self.config,
+            &self.agent,
+            &credentials.user,
+            credentials.password.expose(),
+        )?;
+        Ok(Some(resp))
+    }
+
+    /// Iterate over all the items of a paginated endpoint, such as
+    /// `chargers/EH000001/sessions`, requesting `limit` items per page
+    pub fn paged<T: DeserializeOwned>(&mut self, path: &str, limit: u32) -> Paged<'_, T> {
+        Paged {
+            ctx: self,
+            path: path.to_owned(),
+            limit: limit.max(1),
+            next_page: Some(1),
+            last_page: vec![],
+            items: VecDeque::new(),
+            item: PhantomData,
+        }
+    }
+
+    /// List all sites available to the user
+    pub fn sites(&mut self) -> Result<Vec<Site>, ApiError> {
+        self.get("sites")
+    }
+
+    pub fn site(&mut self, id: SiteId) -> Result<SiteDetails, ApiError> {
+        self.get(&format!("sites/{id}"))
+    }
+
+    /// List all chargers available to the user
+    pub fn chargers(&mut self) -> Result<Vec<Charger>, ApiError> {
+        self.get("chargers")
+    }
+
+    pub fn charger(&mut self, id: &ChargerId) -> Result<Charger, ApiError> {
+        self.get(&format!("chargers/{}", id))
+    }
+
+    /// Profile of the logged in user
+    pub fn profile(&mut self) -> Result<Profile, ApiError> {
+        self.get("accounts/profile")
+    }
+
+    /// Update the profile of the logged in user. The user ID can't be changed.
+    pub fn update_profile(&mut self, profile: &Profile) -> Result<Profile, ApiError> {
+        self.put("accounts/profile", profile)
+    }
+
+    /// Change the password of the logged in user
+    pub fn change_password(&mut self, old: &str, new: &str) -> Result<(), ApiError> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params<'t> {
+            old_password: &'t str,
+            new_password: &'t str,
+        }
+
+        info!("Changing account password");
+        self.post_no_content(
+            "accounts/password",
+            &Params {
+                old_password: old,
+                new_password: new,
+            },
+        )
+    }
+
+    /// List all chargers and equalizers available to the user, grouped by
+    /// site. Much cheaper than walking sites and circuits.
+    pub fn products(&mut self) -> Result<Vec<SiteProducts>, ApiError> {
+        let user_id = self.profile()?.user_id;
+        self.get(&format!("accounts/products?userId={user_id}"))
+    }
+
+    /// Topology of the installation, fetched on first use and then cached
+    pub fn topology(&mut self) -> Result<&Installation, ApiError> {
+        if self.topology.is_none() {
+            let topology = Installation::load(self)?;
+            self.topology = Some(topology);
+        }
+        Ok(self.topology.as_ref().unwrap())
+    }
+
+    /// Fetch the topology of the installation again, replacing the cached one
+    pub fn refresh_topology(&mut self) -> Result<&Installation, ApiError> {
+        self.invalidate_topology();
+        self.topology()
+    }
+
+    /// Forget the cached topology after a change, it will be fetched again on next use
+    pub(crate) fn invalidate_topology(&mut self) {
+        self.topology = None;
+    }
+
+    /// Find a charger by its name, as configured in the app. Falls back to
+    /// fuzzy matching if no charger has this exact name, as long as a single
+    /// charger matches best.
+    pub fn charger_by_name(&mut self, name: &str) -> Result<Option<Charger>, ApiError> {
+        Ok(self.topology()?.charger_by_fuzzy_name(name).cloned())
+    }
+
+    pub fn circuit(&mut self, site_id: SiteId, circuit_id: CircuitId) -> Result<Circuit, ApiError> {
+        self.get(&format!("site/{site_id}/circuit/{circuit_id}"))
+    }
+
+    pub fn circuit_dynamic_current(
+        &mut self,
+        site_id: SiteId,
+        circuit_id: CircuitId,
     ) -> Result<Triphase, ApiError> {
         self.get(&format!(
             "sites/{site_id}/circuits/{circuit_id}/dynamicCurrent"
@@ -502,8 +2184,8 @@ impl Context {
 
     pub fn set_circuit_dynamic_current(
         &mut self,
-        site_id: u32,
-        circuit_id: u32,
+        site_id: SiteId,
+        circuit_id: CircuitId,
         current: SetCurrent,
     ) -> Result<(), ApiError> {
         self.post(
@@ -512,31 +2194,189 @@ impl Context {
         )
     }
 
+    /// Send a request to the API. If the token is rejected, it is refreshed
+    /// and the request sent again, once.
+    fn send<P: Serialize>(
+        &mut self,
+        method: &str,
+        url: &str,
+        body: Option<&P>,
+    ) -> Result<ureq::Response, ApiError> {
+        self.send_with_headers(method, url, body, &[])
+    }
+
+    fn send_with_headers<P: Serialize>(
+        &mut self,
+        method: &str,
+        url: &str,
+        body: Option<&P>,
+        headers: &[(&str, &str)],
+    ) -> Result<ureq::Response, ApiError> {
+        if method != "GET" {
+            if let Some(cache) = &mut self.ttl_cache {
+                cache.clear();
+            }
+        }
+
+        let mut limited = 0;
+        loop {
+            match self.send_once(method, url, body, headers) {
+                Err(ApiError::Server {
+                    status: 429,
+                    retry_after: Some(wait),
+                    ..
+                }) if limited < MAX_RATE_LIMITED_RETRIES && wait <= MAX_RETRY_AFTER => {
+                    limited += 1;
+                    warn!("Rate limited by the server, waiting {wait:?}");
+                    std::thread::sleep(wait);
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn send_once<P: Serialize>(
+        &mut self,
+        method: &str,
+        url: &str,
+        body: Option<&P>,
+        headers: &[(&str, &str)],
+    ) -> Result<ureq::Response, ApiError> {
+        self.check_expired()?;
+        self.throttle()?;
+
+        let agent = self.agent.clone();
+        let call = |auth: &SecretString| {
+            let req = headers.iter().fold(
+                agent
+                    .request(method, url)
+                    .set("Accept", "application/json")
+                    .set("Authorization", auth.expose()),
+                |req, (name, value)| req.set(name, value),
+            );
+            match body {
+                Some(body) => req.send_json(body),
+                None => req.call(),
+            }
+            .map_err(Box::new)
+        };
+
+        match call(&self.auth_header) {
+            Ok(resp) if resp.status() != 401 => Ok(resp),
+            Err(e) if !matches!(*e, ureq::Error::Status(401, _)) => Err((*e).into()),
+            _ => {
+                debug!("Token rejected, refreshing");
+                self.refresh_token()?;
+                call(&self.auth_header).map_err(|e| (*e).into())
+            }
+        }
+    }
+
+    fn get_response(&mut self, path: &str) -> Result<ureq::Response, ApiError> {
+        let etag = self
+            .etags
+            .as_ref()
+            .and_then(|c| c.etag(path))
+            .map(str::to_owned);
+        let headers: Vec<(&str, &str)> =
+            etag.iter().map(|e| ("If-None-Match", e.as_str())).collect();
+        self.get_with_retry(path, &headers)
+    }
+
+    /// GET `path`, sending it again on transient errors as set by the retry policy
+    fn get_with_retry(
+        &mut self,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<ureq::Response, ApiError> {
+        let url = self.config.url(path);
+        let mut retry = 0;
+        loop {
+            match self.send_with_headers::<()>("GET", &url, None, headers) {
+                Err(e) if RetryPolicy::is_transient(&e) => {
+                    let Some(policy) = self.retry.filter(|p| retry + 1 < p.max_attempts) else {
+                        return Err(e);
+                    };
+                    retry += 1;
+                    let delay = policy.delay(retry);
+                    warn!("GET {path} failed ({e}), retrying in {delay:?}");
+                    std::thread::sleep(delay);
+                }
+                other => return other,
+            }
+        }
+    }
+
     #[instrument]
     fn get<T: DeserializeOwned>(&mut self, path: &str) -> Result<T, ApiError> {
-        self.check_expired()?;
-        let url: String = format!("{}{}", API_BASE, path);
-        let req = ureq::get(&url)
-            .set("Accept", "application/json")
-            .set("Authorization", &self.auth_header);
+        if let Some(cached) = self.cached(path) {
+            return cached;
+        }
+        let resp = self.get_response(path)?;
+        self.parse_response("GET", path, None::<&()>, resp)
+    }
 
-        let mut resp = req.clone().call()?;
+    /// GET a JSON array, decoding its items one at a time as they are read
+    /// from the connection instead of buffering the whole response. Such
+    /// responses bypass the caches and the schema tracking.
+    fn get_records<T: DeserializeOwned>(&mut self, path: &str) -> Result<Records<T>, ApiError> {
+        let resp = self.get_with_retry(path, &[])?;
+        Ok(Records::new(resp.into_reader()))
+    }
 
-        if resp.status() == 401 {
-            self.refresh_token()?;
-            resp = req.call()?
+    /// GET a resource at `path`, relative to the API base URL, returning
+    /// `None` if it doesn't exist (404 Not Found or 204 No Content)
+    pub fn maybe_get<T: DeserializeOwned>(&mut self, path: &str) -> Result<Option<T>, ApiError> {
+        self.maybe_get_with(path, MissingPolicy::default())
+    }
+
+    /// GET a resource, returning `None` for the statuses selected by `policy`
+    pub fn maybe_get_with<T: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        policy: MissingPolicy,
+    ) -> Result<Option<T>, ApiError> {
+        if let Some(cached) = self.cached(path) {
+            return cached.map(Some);
         }
+        let resp = self.get_response(path);
+        self.parse_maybe("GET", path, None::<&()>, resp, policy)
+    }
 
-        resp.into_json_with_error()
+    /// POST to `path`, relative to the API base URL, returning `None` if the
+    /// target doesn't exist (404 Not Found) or nothing was returned (204 No Content)
+    pub fn maybe_post<T: DeserializeOwned, P: Serialize>(
+        &mut self,
+        path: &str,
+        params: &P,
+    ) -> Result<Option<T>, ApiError> {
+        self.maybe_post_with(path, params, MissingPolicy::default())
     }
 
-    fn maybe_get<T: DeserializeOwned>(&mut self, path: &str) -> Result<Option<T>, ApiError> {
-        match self.get(path) {
-            Ok(r) => Ok(Some(r)),
-            Err(ApiError::Ureq(e)) => match &*e {
-                ureq::Error::Status(404, _) => Ok(None),
-                _ => Err(ApiError::Ureq(e)),
-            },
+    /// POST to `path`, returning `None` for the statuses selected by `policy`
+    pub fn maybe_post_with<T: DeserializeOwned, P: Serialize>(
+        &mut self,
+        path: &str,
+        params: &P,
+        policy: MissingPolicy,
+    ) -> Result<Option<T>, ApiError> {
+        let url = self.config.url(path);
+        let resp = self.send("POST", &url, Some(params));
+        self.parse_maybe("POST", path, Some(params), resp, policy)
+    }
+
+    fn parse_maybe<T: DeserializeOwned, P: Serialize>(
+        &mut self,
+        method: &'static str,
+        path: &str,
+        request: Option<&P>,
+        resp: Result<ureq::Response, ApiError>,
+        policy: MissingPolicy,
+    ) -> Result<Option<T>, ApiError> {
+        match resp {
+            Ok(resp) if policy.is_missing(resp.status()) => Ok(None),
+            Ok(resp) => self.parse_response(method, path, request, resp).map(Some),
+            Err(ApiError::Server { status, .. }) if policy.is_missing(status) => Ok(None),
             Err(other) => Err(other),
         }
     }
@@ -546,138 +2386,1551 @@ impl Context {
         path: &str,
         params: &P,
     ) -> Result<T, ApiError> {
-        let url: String = format!("{}{}", API_BASE, path);
-        self.post_raw(&url, params)
+        let url = self.config.url(path);
+        self.post_raw(&url, path, params)
     }
 
+    /// POST to an absolute `url`, such as one outside the REST API. `path`
+    /// names the endpoint for the schema tracking and the captured bodies.
     pub(crate) fn post_raw<T: DeserializeOwned, P: Serialize>(
         &mut self,
         url: &str,
+        path: &str,
         params: &P,
     ) -> Result<T, ApiError> {
-        self.check_expired()?;
-        let req = ureq::post(url)
-            .set("Accept", "application/json")
-            .set("Authorization", &self.auth_header);
+        let resp = self.send("POST", url, Some(params))?;
+        self.parse_response("POST", path, Some(params), resp)
+    }
 
-        let mut resp = req.clone().send_json(params)?;
+    /// POST to an endpoint, ignoring the response body, if any, such as for
+    /// commands answered with 202 Accepted
+    pub(crate) fn post_no_content<P: Serialize>(
+        &mut self,
+        path: &str,
+        params: &P,
+    ) -> Result<(), ApiError> {
+        let url = self.config.url(path);
+        self.send("POST", &url, Some(params))?;
+        Ok(())
+    }
 
-        if resp.status() == 401 {
-            self.refresh_token()?;
-            resp = req.send_json(params)?
+    pub(crate) fn put<T: DeserializeOwned, P: Serialize>(
+        &mut self,
+        path: &str,
+        params: &P,
+    ) -> Result<T, ApiError> {
+        let url = self.config.url(path);
+        let resp = self.send("PUT", &url, Some(params))?;
+        self.parse_response("PUT", path, Some(params), resp)
+    }
+
+    /// Delete a resource. The response body, if any, is ignored.
+    pub(crate) fn delete(&mut self, path: &str) -> Result<(), ApiError> {
+        let url = self.config.url(path);
+        self.send::<()>("DELETE", &url, None)?;
+        Ok(())
+    }
+
+    /// Delete the resource at `path`, relative to the API base URL, returning
+    /// `false` if it didn't exist (404 Not Found)
+    pub fn maybe_delete(&mut self, path: &str) -> Result<bool, ApiError> {
+        self.maybe_delete_with(path, MissingPolicy::default())
+    }
+
+    /// Delete a resource, returning `false` for the error statuses selected
+    /// by `policy`. A successful deletion without content still returns `true`.
+    pub fn maybe_delete_with(
+        &mut self,
+        path: &str,
+        policy: MissingPolicy,
+    ) -> Result<bool, ApiError> {
+        match self.delete(path) {
+            Ok(()) => Ok(true),
+            Err(ApiError::Server { status, .. }) if policy.is_missing(status) => Ok(false),
+            Err(other) => Err(other),
+        }
+    }
+
+    #[cfg_attr(not(feature = "debug-bodies"), allow(unused_variables))]
+    fn parse_response<T: DeserializeOwned, P: Serialize>(
+        &mut self,
+        method: &'static str,
+        path: &str,
+        request: Option<&P>,
+        resp: ureq::Response,
+    ) -> Result<T, ApiError> {
+        #[cfg(feature = "debug-bodies")]
+        let status = resp.status();
+
+        let cached = match &mut self.etags {
+            Some(cache) if resp.status() == 304 => cache.revalidated(path),
+            _ => None,
+        };
+        let etag = resp.header("ETag").map(str::to_owned);
+
+        let response = match cached {
+            Some(response) => response,
+            None => {
+                // Empty bodies, as sent with 202 Accepted, decode as `()` or `None`
+                let mut body = String::new();
+                resp.into_reader().read_to_string(&mut body)?;
+                let response: serde_json::Value = if body.trim().is_empty() {
+                    serde_json::Value::Null
+                } else {
+                    serde_json::from_str(&body).map_err(io::Error::from)?
+                };
+                if let (Some(cache), Some(etag), "GET") = (&mut self.etags, &etag, method) {
+                    cache.insert(path, etag, &response);
+                }
+                response
+            }
+        };
+        if let (Some(cache), "GET") = (&mut self.ttl_cache, method) {
+            cache.insert(path, &response, Instant::now());
+        }
+        let parsed = self.schema.deserialize(path, &response);
+
+        #[cfg(feature = "debug-bodies")]
+        if let Some(capture) = self.body_capture.as_mut().filter(|c| (c.filter)(path)) {
+            let request = request
+                .and_then(|r| serde_json::to_value(r).ok())
+                .map(redact);
+            (capture.callback)(&CapturedBodies {
+                method,
+                path,
+                status,
+                request: request.as_ref(),
+                response: &redact(response.clone()),
+                error: parsed.as_ref().err(),
+            });
         }
 
-        resp.into_json_with_error()
+        parsed.map_err(|e| ApiError::UnexpectedData(response, e))
+    }
+
+    /// Register a callback notified when a response doesn't match the
+    /// expected schema, which usually means Easee changed its API.
+    pub fn on_schema_event<F: FnMut(&SchemaEvent) + Send + 'static>(mut self, callback: F) -> Self {
+        self.schema.set_callback(Box::new(callback));
+        self
+    }
+
+    /// Schema version observed for an endpoint, such as `chargers/{}/state`.
+    /// It is bumped every time unknown fields appear in its responses.
+    pub fn schema_version(&self, endpoint: &str) -> Option<u32> {
+        self.schema.version(endpoint)
+    }
+
+    /// Capture request and response bodies of the endpoints matching `filter`,
+    /// and hand them over to `callback`. Tokens and passwords are redacted
+    /// before the callback sees them.
+    #[cfg(feature = "debug-bodies")]
+    pub fn capture_bodies<F, C>(mut self, filter: F, callback: C) -> Self
+    where
+        F: Fn(&str) -> bool + Send + 'static,
+        C: FnMut(&CapturedBodies) + Send + 'static,
+    {
+        self.body_capture = Some(BodyCapture {
+            filter: Box::new(filter),
+            callback: Box::new(callback),
+        });
+        self
     }
 }
 
+#[cfg(feature = "debug-bodies")]
+struct BodyCapture {
+    filter: Box<dyn Fn(&str) -> bool + Send>,
+    callback: Box<dyn FnMut(&CapturedBodies) + Send>,
+}
+
+/// Request and response bodies of an API call, with secrets redacted
+#[cfg(feature = "debug-bodies")]
+#[derive(Debug)]
+pub struct CapturedBodies<'a> {
+    pub method: &'static str,
+    pub path: &'a str,
+    pub status: u16,
+    pub request: Option<&'a serde_json::Value>,
+    pub response: &'a serde_json::Value,
+
+    /// Deserialization error, if the response didn't match the expected format
+    pub error: Option<&'a serde_json::Error>,
+}
+
 /// Energy meter reading
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MeterReading {
     /// ID of the charger
-    pub charger_id: String,
+    pub charger_id: ChargerId,
 
-    /// Lifetime consumed energy, in kWh
-    pub life_time_energy: f64,
+    /// Lifetime consumed energy
+    pub life_time_energy: KilowattHours,
 }
 
-impl Site {
-    /// Read all energy meters from the given site
-    pub fn lifetime_energy(&self, ctx: &mut Context) -> Result<Vec<MeterReading>, ApiError> {
-        ctx.get(&format!("sites/{}/energy", self.id))
-    }
+/// Energy consumption aggregated over a month or a year
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodUsage {
+    pub year: i32,
 
-    pub fn details(&self, ctx: &mut Context) -> Result<SiteDetails, ApiError> {
-        ctx.get(&format!("sites/{}", self.id))
-    }
+    /// Month of the year, starting at 1. Absent for yearly aggregates.
+    pub month: Option<u32>,
+
+    /// Consumed energy
+    #[serde(rename = "totalEnergyUsage")]
+    pub energy_kwh: KilowattHours,
+
+    /// Cost of the consumed energy, if a price is configured on the site
+    #[serde(rename = "totalCost")]
+    pub cost: Option<f64>,
+    pub currency_id: Option<String>,
 }
 
-impl Circuit {
-    fn dynamic_current_path(&self) -> String {
-        format!("sites/{}/circuits/{}/dynamicCurrent", self.site_id, self.id)
+impl PeriodUsage {
+    /// Cost of the consumed energy, with its currency
+    pub fn total_cost(&self) -> Option<Money> {
+        Some(Money::new(self.cost?, self.currency_id.as_deref()?))
     }
+}
 
-    pub fn dynamic_current(&self, ctx: &mut Context) -> Result<Triphase, ApiError> {
-        ctx.circuit_dynamic_current(self.site_id, self.id)
-    }
+/// Energy consumed during a time slot
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct EnergyUsage {
+    /// Start of the time slot
+    pub date: UtcDateTime,
 
-    pub fn set_dynamic_current(
-        &self,
-        ctx: &mut Context,
-        current: SetCurrent,
-    ) -> Result<(), ApiError> {
-        ctx.post(&self.dynamic_current_path(), &current)
-    }
+    /// Consumed energy
+    #[serde(rename = "totalEnergy")]
+    pub energy_kwh: KilowattHours,
 }
 
-impl Charger {
-    /// Enable "smart charging" on the charger. This just turns the LED blue, and disables basic charging plans.
-    pub fn enable_smart_charging(&self, ctx: &mut Context) -> Result<(), ApiError> {
-        let url = format!("chargers/{}/commands/smart_charging", &self.id);
-        ctx.post(&url, &())
-    }
+/// Energy consumption of one charger, over several time slots
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargerEnergyUsage {
+    pub charger_id: ChargerId,
 
-    /// Read the state of a charger
-    pub fn state(&self, ctx: &mut Context) -> Result<ChargerState, ApiError> {
-        let url = format!("chargers/{}/state", self.id);
-        ctx.get(&url)
-    }
+    #[serde(rename = "energyUsage")]
+    pub usage: Vec<EnergyUsage>,
+}
 
-    /// Read info about the ongoing charging session
-    pub fn ongoing_session(&self, ctx: &mut Context) -> Result<Option<ChargingSession>, ApiError> {
-        ctx.maybe_get(&format!("chargers/{}/sessions/ongoing", &self.id))
-    }
+/// One page of a paginated list, either bare or wrapped in an object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Page<T> {
+    List(Vec<T>),
+    Wrapped { data: Vec<T> },
+}
 
-    /// Read info about the last charging session (not including ongoing one)
-    pub fn latest_session(&self, ctx: &mut Context) -> Result<Option<ChargingSession>, ApiError> {
-        ctx.maybe_get(&format!("chargers/{}/sessions/latest", &self.id))
-    }
+/// Iterator over all the items of a paginated endpoint, fetching the pages
+/// one by one as needed. See [`Context::paged`].
+pub struct Paged<'c, T> {
+    ctx: &'c mut Context,
+    path: String,
+    limit: u32,
+    next_page: Option<u32>,
+    last_page: Vec<serde_json::Value>,
+    items: VecDeque<serde_json::Value>,
+    item: PhantomData<fn() -> T>,
+}
 
-    fn command(&self, ctx: &mut Context, command: &str) -> Result<CommandReply, ApiError> {
-        ctx.post(&format!("chargers/{}/commands/{}", self.id, command), &())
+impl<T: DeserializeOwned> Paged<'_, T> {
+    /// Next item, fetching the pages with `fetch` from their paths
+    fn next_with<F>(&mut self, mut fetch: F) -> Option<Result<T, ApiError>>
+    where
+        F: FnMut(&mut Context, &str) -> Result<Option<Page<serde_json::Value>>, ApiError>,
+    {
+        if self.items.is_empty() {
+            let page = self.next_page?;
+            let separator = if self.path.contains('?') { '&' } else { '?' };
+            let path = format!("{}{separator}page={page}&limit={}", self.path, self.limit);
+            let items = match fetch(self.ctx, &path) {
+                Ok(Some(Page::List(items) | Page::Wrapped { data: items })) => items,
+                Ok(None) => vec![],
+                Err(e) => {
+                    self.next_page = None;
+                    return Some(Err(e));
+                }
+            };
+            // An empty or short page is the last one. Endpoints ignoring the
+            // page number send the first one again, stop there too.
+            if items.is_empty() || items == self.last_page {
+                self.next_page = None;
+                return None;
+            }
+            self.next_page = (items.len() >= self.limit as usize).then_some(page + 1);
+            self.last_page = items.clone();
+            self.items = items.into();
+        }
+        let item = self.items.pop_front()?;
+        Some(serde_json::from_value(item.clone()).map_err(|e| ApiError::UnexpectedData(item, e)))
     }
+}
 
-    pub fn start(&self, ctx: &mut Context) -> Result<(), ApiError> {
-        self.command(ctx, "start_charging")?;
-        Ok(())
+impl<T: DeserializeOwned> Iterator for Paged<'_, T> {
+    type Item = Result<T, ApiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with(|ctx, path| ctx.maybe_get(path))
     }
+}
 
-    pub fn pause(&self, ctx: &mut Context) -> Result<(), ApiError> {
-        self.command(ctx, "pause_charging")?;
-        Ok(())
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RecordsState {
+    Start,
+    First,
+    Rest,
+    Done,
+}
+
+/// Iterator over the items of a JSON array response, decoded one at a time
+/// while reading the body, such as [`Charger::hourly_usage_iter`]. Peak memory
+/// stays at about one item, however long the response.
+pub struct Records<T> {
+    reader: BufReader<Box<dyn Read + Send + Sync>>,
+    state: RecordsState,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Records<T> {
+    pub(crate) fn new(reader: Box<dyn Read + Send + Sync>) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            state: RecordsState::Start,
+            item: PhantomData,
+        }
     }
 
-    pub fn resume(&self, ctx: &mut Context) -> Result<(), ApiError> {
-        self.command(ctx, "resume_charging")?;
-        Ok(())
+    /// Next byte that isn't whitespace, left unconsumed
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            match self.reader.fill_buf()?.first() {
+                Some(b) if b.is_ascii_whitespace() => self.reader.consume(1),
+                other => return Ok(other.copied()),
+            }
+        }
     }
 
-    pub fn stop(&self, ctx: &mut Context) -> Result<(), ApiError> {
-        self.command(ctx, "stop_charging")?;
-        Ok(())
+    fn next_record(&mut self) -> io::Result<Option<T>> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let next = self.peek()?;
+        match (self.state, next) {
+            // An empty body is an empty list
+            (RecordsState::Start, None) => return Ok(None),
+            (RecordsState::Start, Some(b'[')) => {
+                self.reader.consume(1);
+                self.state = RecordsState::First;
+                return self.next_record();
+            }
+            (RecordsState::Start, _) => return Err(invalid("expected a JSON array")),
+            (RecordsState::First | RecordsState::Rest, Some(b']')) => return Ok(None),
+            (RecordsState::First, _) => (),
+            (RecordsState::Rest, Some(b',')) => self.reader.consume(1),
+            _ => return Err(invalid("expected ',' or ']' in a JSON array")),
+        }
+        self.state = RecordsState::Rest;
+
+        // Items are objects, which end on their closing brace: nothing past
+        // it is read from the reader, and the stream can resume right after.
+        serde_json::Deserializer::from_reader(&mut self.reader)
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?
+            .map(Some)
+            .map_err(io::Error::from)
     }
 }
 
-#[cfg(test)]
-mod test {
-    use std::time::{Duration, Instant};
+impl<T: DeserializeOwned> Iterator for Records<T> {
+    type Item = Result<T, ApiError>;
 
-    use super::Context;
-    #[test]
-    fn token_save() {
-        let ctx = Context {
-            auth_header: "Bearer aaaaaaa0".to_owned(),
-            refresh_token: "abcdef".to_owned(),
-            token_expiration: Instant::now() + Duration::from_secs(1234),
-            on_refresh: None,
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.state == RecordsState::Done {
+            return None;
+        }
+        let record = self.next_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.state = RecordsState::Done;
+        }
+        record.map(|r| r.map_err(ApiError::from))
+    }
+}
+
+impl SiteDetails {
+    /// Read the ongoing charging session of each charger of the site, up to
+    /// `parallelism` at once. A failure for one charger doesn't prevent
+    /// reading the others, see [`fleet::ongoing_sessions`].
+    pub fn ongoing_sessions(
+        &self,
+        ctx: &mut Context,
+        parallelism: usize,
+    ) -> Result<OngoingSessions, ApiError> {
+        let chargers: Vec<Charger> = self
+            .circuits
+            .iter()
+            .flat_map(|c| c.chargers.iter().cloned())
+            .collect();
+        fleet::ongoing_sessions(ctx, &chargers, parallelism)
+    }
+
+    /// Move a charger of the site to another of its circuits, then refresh
+    /// the details of the site
+    pub fn move_charger(
+        &mut self,
+        ctx: &mut Context,
+        charger_id: &ChargerId,
+        circuit_id: CircuitId,
+    ) -> Result<(), ApiError> {
+        if !self.circuits.iter().any(|c| c.id == circuit_id) {
+            return Err(ApiError::InvalidID(circuit_id.to_string()));
+        }
+        ctx.post_no_content(
+            &format!(
+                "sites/{}/circuits/{}/chargers/{}",
+                self.site.id, circuit_id, charger_id
+            ),
+            &(),
+        )?;
+        ctx.invalidate_topology();
+        *self = ctx.get(&format!("sites/{}", self.site.id))?;
+        Ok(())
+    }
+}
+
+impl Site {
+    /// Read all energy meters from the given site
+    pub fn lifetime_energy(&self, ctx: &mut Context) -> Result<Vec<MeterReading>, ApiError> {
+        ctx.get(&format!("sites/{}/energy", self.id))
+    }
+
+    /// Read the energy consumption of each charger of the site between the given dates, hour by hour
+    pub fn hourly_energy(
+        &self,
+        ctx: &mut Context,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Vec<ChargerEnergyUsage>, ApiError> {
+        ctx.get(&format!(
+            "sites/{}/energy/{}/{}",
+            self.id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    pub fn details(&self, ctx: &mut Context) -> Result<SiteDetails, ApiError> {
+        ctx.get(&format!("sites/{}", self.id))
+    }
+
+    /// Read the ongoing charging session of each charger of the site, up to
+    /// `parallelism` at once. This costs one API call per charger, plus one.
+    pub fn ongoing_sessions(
+        &self,
+        ctx: &mut Context,
+        parallelism: usize,
+    ) -> Result<OngoingSessions, ApiError> {
+        self.details(ctx)?.ongoing_sessions(ctx, parallelism)
+    }
+
+    /// Read the energy price and contact info configured on the site
+    pub fn settings(&self, ctx: &mut Context) -> Result<SiteSettings, ApiError> {
+        ctx.get(&format!("sites/{}/settings", self.id))
+    }
+
+    /// List the equalizers installed on the site
+    pub fn equalizers(&self, ctx: &mut Context) -> Result<Vec<Equalizer>, ApiError> {
+        ctx.get(&format!("sites/{}/equalizers", self.id))
+    }
+
+    /// List the users having access to the site
+    pub fn users(&self, ctx: &mut Context) -> Result<Vec<SiteUser>, ApiError> {
+        ctx.get(&format!("sites/{}/users", self.id))
+    }
+
+    /// Grant access to the site to the user with the given email address
+    pub fn invite_user(
+        &self,
+        ctx: &mut Context,
+        email: &str,
+        role: LevelOfAccess,
+    ) -> Result<SiteUser, ApiError> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params<'t> {
+            email: &'t str,
+            role: LevelOfAccess,
+        }
+
+        ctx.post(&format!("sites/{}/users", self.id), &Params { email, role })
+    }
+
+    /// Revoke the access of a user to the site
+    pub fn remove_user(&self, ctx: &mut Context, user_id: u32) -> Result<(), ApiError> {
+        ctx.delete(&format!("sites/{}/users/{}", self.id, user_id))
+    }
+}
+
+impl Circuit {
+    fn dynamic_current_path(&self) -> String {
+        format!("sites/{}/circuits/{}/dynamicCurrent", self.site_id, self.id)
+    }
+
+    pub fn dynamic_current(&self, ctx: &mut Context) -> Result<Triphase, ApiError> {
+        ctx.circuit_dynamic_current(self.site_id, self.id)
+    }
+
+    pub fn set_dynamic_current(
+        &self,
+        ctx: &mut Context,
+        current: SetCurrent,
+    ) -> Result<(), ApiError> {
+        current.validate(Amperes(self.rated_current))?;
+        ctx.post(&self.dynamic_current_path(), &current)
+    }
+
+    /// Stop charging on the whole circuit by setting its dynamic current to
+    /// zero. The previous current is kept in the context for [`Circuit::restore`].
+    /// Pausing an already paused circuit keeps the current saved the first time.
+    /// A circuit already without current, such as one paused by another
+    /// process, has nothing to restore and is not recorded as paused.
+    pub fn pause(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        if ctx.paused_current(self.id).is_none() {
+            let current = self.dynamic_current(ctx)?;
+            if current.max() > Amperes(0.0) {
+                ctx.set_paused_current(self.id, current);
+                ctx.notify_pause(self.id);
+            } else {
+                warn!("Circuit {} has no dynamic current to restore", self.id);
+            }
+        }
+        self.set_dynamic_current(
+            ctx,
+            SetCurrent {
+                time_to_live: None,
+                current: Triphase::default(),
+            },
+        )
+    }
+
+    /// Put back the dynamic current saved by [`Circuit::pause`]. Returns
+    /// whether the circuit was paused.
+    pub fn restore(&self, ctx: &mut Context) -> Result<bool, ApiError> {
+        let Some(current) = ctx.paused_current(self.id) else {
+            return Ok(false);
         };
+        self.set_dynamic_current(
+            ctx,
+            SetCurrent {
+                time_to_live: None,
+                current,
+            },
+        )?;
+        ctx.paused_circuits.remove(&self.id);
+        ctx.notify_pause(self.id);
+        Ok(true)
+    }
+
+    /// Attach a new charger to the circuit, given its serial number and the
+    /// PIN code printed on it
+    pub fn pair_charger(
+        &self,
+        ctx: &mut Context,
+        serial: &ChargerId,
+        pin: &str,
+    ) -> Result<(), ApiError> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params<'t> {
+            id: &'t str,
+            pin_code: &'t str,
+        }
+
+        ctx.post_no_content(
+            &format!("sites/{}/circuits/{}/chargers", self.site_id, self.id),
+            &Params {
+                id: serial,
+                pin_code: pin,
+            },
+        )?;
+        ctx.invalidate_topology();
+        Ok(())
+    }
+
+    /// Detach a charger from the circuit
+    pub fn unpair_charger(
+        &self,
+        ctx: &mut Context,
+        charger_id: &ChargerId,
+    ) -> Result<(), ApiError> {
+        ctx.delete(&format!(
+            "sites/{}/circuits/{}/chargers/{}",
+            self.site_id, self.id, charger_id
+        ))?;
+        ctx.invalidate_topology();
+        Ok(())
+    }
+
+    fn settings_path(&self) -> String {
+        format!("sites/{}/circuits/{}/settings", self.site_id, self.id)
+    }
+
+    /// Read the current limits of the circuit
+    pub fn settings(&self, ctx: &mut Context) -> Result<CircuitSettings, ApiError> {
+        ctx.get(&self.settings_path())
+    }
+
+    /// Change the current limits of the circuit. Fields set to `None` are left untouched.
+    pub fn update_settings(
+        &self,
+        ctx: &mut Context,
+        settings: &CircuitSettings,
+    ) -> Result<(), ApiError> {
+        ctx.post_no_content(&self.settings_path(), settings)
+    }
+}
+
+impl Equalizer {
+    /// Read the configuration of the equalizer
+    pub fn config(&self, ctx: &mut Context) -> Result<EqualizerConfig, ApiError> {
+        ctx.get(&format!("equalizers/{}/config", self.id))
+    }
+
+    /// Change the configuration of the equalizer. Fields set to `None` are left untouched.
+    pub fn update_config(
+        &self,
+        ctx: &mut Context,
+        config: &EqualizerConfig,
+    ) -> Result<(), ApiError> {
+        ctx.post_no_content(&format!("equalizers/{}/settings", self.id), config)
+    }
+
+    fn command(&self, ctx: &mut Context, command: &str) -> Result<Option<CommandReply>, ApiError> {
+        ctx.post(&format!("equalizers/{}/commands/{}", self.id, command), &())
+    }
+
+    pub fn reboot(&self, ctx: &mut Context) -> Result<Option<CommandReply>, ApiError> {
+        self.command(ctx, "reboot")
+    }
+
+    /// Ask the equalizer to install the latest firmware
+    pub fn update_firmware(&self, ctx: &mut Context) -> Result<Option<CommandReply>, ApiError> {
+        self.command(ctx, "update_firmware")
+    }
+}
+
+impl Charger {
+    /// Enable "smart charging" on the charger. This just turns the LED blue, and disables basic charging plans.
+    pub fn enable_smart_charging(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        let url = format!("chargers/{}/commands/smart_charging", &self.id);
+        ctx.post(&url, &())
+    }
+
+    /// Read the state of a charger
+    pub fn state(&self, ctx: &mut Context) -> Result<ChargerState, ApiError> {
+        let url = format!("chargers/{}/state", self.id);
+        ctx.get(&url)
+    }
+
+    /// Poll the state of the charger until it reaches the `target` operating
+    /// mode. Returns whether it did before `timeout` expired.
+    pub fn wait_for_op_mode(
+        &self,
+        ctx: &mut Context,
+        target: ChargerOpMode,
+        timeout: Duration,
+    ) -> Result<bool, ApiError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.state(ctx)?.charger_op_mode == target {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            std::thread::sleep(OP_MODE_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Read the current value of all the observations of the charger, decoded
+    /// the same way as the events of the observation stream
+    pub fn observations(&self, ctx: &mut Context) -> Result<Vec<Event>, ApiError> {
+        self.fetch_observations(ctx, &format!("state/{}/observations", self.id))
+    }
+
+    /// Read the current value of only the given observations, such as
+    /// [`ids::TOTAL_POWER`](crate::observation::ids::TOTAL_POWER). Much
+    /// lighter than [`Charger::state`] for pollers needing a few values.
+    pub fn observations_by_id(
+        &self,
+        ctx: &mut Context,
+        ids: &[u16],
+    ) -> Result<Vec<Event>, ApiError> {
+        let ids: Vec<String> = ids.iter().map(u16::to_string).collect();
+        self.fetch_observations(
+            ctx,
+            &format!("state/{}/observations?ids={}", self.id, ids.join(",")),
+        )
+    }
+
+    fn fetch_observations(&self, ctx: &mut Context, path: &str) -> Result<Vec<Event>, ApiError> {
+        let updates: Vec<ProductUpdate> = ctx.get(path)?;
+        updates
+            .into_iter()
+            .map(|mut update| {
+                if update.mid.is_empty() {
+                    update.mid = self.id.to_string();
+                }
+                Ok(decode_update(update)?)
+            })
+            .collect()
+    }
+
+    /// Read the energy meter of the charger. The charger is asked to report a
+    /// fresh value first, but the reading may still lag by a few seconds.
+    pub fn lifetime_energy(&self, ctx: &mut Context) -> Result<MeterReading, ApiError> {
+        self.command(ctx, "poll_lifetimeenergy")?;
+        let state = self.state(ctx)?;
+        Ok(MeterReading {
+            charger_id: self.id.clone(),
+            life_time_energy: state.lifetime_energy,
+        })
+    }
+
+    /// Read info about the ongoing charging session
+    pub fn ongoing_session(&self, ctx: &mut Context) -> Result<Option<ChargingSession>, ApiError> {
+        ctx.maybe_get(&format!("chargers/{}/sessions/ongoing", &self.id))
+    }
+
+    /// Read info about the last charging session (not including ongoing one)
+    pub fn latest_session(&self, ctx: &mut Context) -> Result<Option<ChargingSession>, ApiError> {
+        ctx.maybe_get(&format!("chargers/{}/sessions/latest", &self.id))
+    }
+
+    /// Read the energy consumption of the charger, aggregated by month
+    pub fn monthly_usage(&self, ctx: &mut Context) -> Result<Vec<PeriodUsage>, ApiError> {
+        ctx.get(&format!("chargers/{}/usage/monthly", self.id))
+    }
+
+    /// Read the energy consumption of the charger, aggregated by year
+    pub fn yearly_usage(&self, ctx: &mut Context) -> Result<Vec<PeriodUsage>, ApiError> {
+        ctx.get(&format!("chargers/{}/usage/yearly", self.id))
+    }
+
+    /// Read the energy consumption of the charger between the given dates, hour by hour
+    pub fn hourly_usage(
+        &self,
+        ctx: &mut Context,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Vec<EnergyUsage>, ApiError> {
+        ctx.get(&format!(
+            "chargers/{}/usage/hourly/{}/{}",
+            self.id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    /// Same as [`Charger::hourly_usage`], decoding the hours one at a time
+    /// while they are downloaded, for long periods on constrained devices
+    pub fn hourly_usage_iter(
+        &self,
+        ctx: &mut Context,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Records<EnergyUsage>, ApiError> {
+        ctx.get_records(&format!(
+            "chargers/{}/usage/hourly/{}/{}",
+            self.id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    /// Read the energy consumption of the charger between the given dates, day by day
+    pub fn daily_usage(
+        &self,
+        ctx: &mut Context,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Vec<EnergyUsage>, ApiError> {
+        ctx.get(&format!(
+            "chargers/{}/usage/daily/{}/{}",
+            self.id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    /// Read the past values of an observation between the given dates
+    pub fn observation_history(
+        &self,
+        ctx: &mut Context,
+        observation_id: u16,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Vec<ObservationSample>, ApiError> {
+        ctx.get(&format!(
+            "chargers/{}/observations/{}/{}/{}",
+            self.id,
+            observation_id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    /// Same as [`Charger::observation_history`], decoding the samples one at
+    /// a time while they are downloaded
+    pub fn observation_history_iter(
+        &self,
+        ctx: &mut Context,
+        observation_id: u16,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Records<ObservationSample>, ApiError> {
+        ctx.get_records(&format!(
+            "chargers/{}/observations/{}/{}/{}",
+            self.id,
+            observation_id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    /// List the charging sessions that took place between the given dates
+    pub fn sessions(
+        &self,
+        ctx: &mut Context,
+        from: UtcDateTime,
+        to: UtcDateTime,
+    ) -> Result<Vec<ChargingSession>, ApiError> {
+        ctx.get(&format!(
+            "chargers/{}/sessions/{}/{}",
+            self.id,
+            from.to_url_param(),
+            to.to_url_param()
+        ))
+    }
+
+    /// Change the settings of the charger. Fields set to `None` are left untouched.
+    pub fn update_settings(
+        &self,
+        ctx: &mut Context,
+        settings: &ChargerSettings,
+    ) -> Result<(), ApiError> {
+        ctx.post_no_content(&format!("chargers/{}/settings", self.id), settings)
+    }
+
+    /// Select single-phase, three-phase or automatic charging
+    pub fn set_phase_mode(&self, ctx: &mut Context, mode: PhaseMode) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                phase_mode: Some(mode),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Limit the current of the charger, in A
+    pub fn set_dynamic_current(&self, ctx: &mut Context, current: f64) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                dynamic_charger_current: Some(current),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Change the metadata of the charger
+    pub fn update(&self, ctx: &mut Context, update: &ChargerUpdate) -> Result<(), ApiError> {
+        ctx.post_no_content(&format!("chargers/{}/update", self.id), update)?;
+        ctx.invalidate_topology();
+        Ok(())
+    }
+
+    pub fn set_name(&self, ctx: &mut Context, name: &str) -> Result<(), ApiError> {
+        self.update(
+            ctx,
+            &ChargerUpdate {
+                name: Some(name.to_owned()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Set the color of the charger, as shown in the app
+    pub fn set_color(&self, ctx: &mut Context, color: i32) -> Result<(), ApiError> {
+        self.update(
+            ctx,
+            &ChargerUpdate {
+                color: Some(color),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Installed and latest available firmware versions
+    pub fn firmware(&self, ctx: &mut Context) -> Result<FirmwareStatus, ApiError> {
+        ctx.get(&format!("chargers/{}/firmware", self.id))
+    }
+
+    fn charge_plan_path(&self) -> String {
+        format!("chargers/{}/basic_charge_plan", self.id)
+    }
+
+    /// Read the charging schedule of the charger, if any
+    pub fn charge_plan(&self, ctx: &mut Context) -> Result<Option<ChargePlan>, ApiError> {
+        ctx.maybe_get(&self.charge_plan_path())
+    }
+
+    /// Schedule charging, such as a delayed start or a departure time
+    pub fn set_charge_plan(&self, ctx: &mut Context, plan: &ChargePlan) -> Result<(), ApiError> {
+        let plan = ChargePlan {
+            id: Some(self.id.to_string()),
+            ..plan.clone()
+        };
+        ctx.post_no_content(&self.charge_plan_path(), &plan)
+    }
+
+    /// Remove the charging schedule, returning `false` if there was none
+    pub fn delete_charge_plan(&self, ctx: &mut Context) -> Result<bool, ApiError> {
+        ctx.maybe_delete(&self.charge_plan_path())
+    }
+
+    /// Let Easee shift charging to the cheapest hours, based on spot prices
+    pub fn set_smart_charging(&self, ctx: &mut Context, enabled: bool) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                smart_charging: Some(enabled),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// List the keys registered on the charger
+    pub fn keys(&self, ctx: &mut Context) -> Result<Vec<ChargingKey>, ApiError> {
+        ctx.get(&format!("chargers/{}/keys", self.id))
+    }
+
+    /// Register a key, allowing it to charge on the charger
+    pub fn add_key(&self, ctx: &mut Context, name: &str, token_id: &str) -> Result<(), ApiError> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params<'t> {
+            name: &'t str,
+            token_id: &'t str,
+        }
+
+        ctx.post_no_content(
+            &format!("chargers/{}/keys", self.id),
+            &Params { name, token_id },
+        )
+    }
+
+    /// Revoke a key
+    pub fn delete_key(&self, ctx: &mut Context, token_id: &str) -> Result<(), ApiError> {
+        ctx.delete(&format!("chargers/{}/keys/{}", self.id, token_id))
+    }
+
+    /// Read the configuration of the charger
+    pub fn config(&self, ctx: &mut Context) -> Result<ChargerConfig, ApiError> {
+        ctx.get(&format!("chargers/{}/config", self.id))
+    }
+
+    /// Require charging to be authorized, so that the charger can't be used
+    /// without a registered key or the app
+    pub fn set_authorization_required(
+        &self,
+        ctx: &mut Context,
+        required: bool,
+    ) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                authorization_required: Some(required),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Enable or disable the charger. A disabled charger refuses to charge.
+    pub fn set_enabled(&self, ctx: &mut Context, enabled: bool) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                enabled: Some(enabled),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Set the brightness of the LED strip, in percent
+    pub fn set_led_brightness(&self, ctx: &mut Context, percent: u8) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                led_strip_brightness: Some(percent.min(100)),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Brightness of the LED strip, in percent
+    pub fn led_brightness(&self, ctx: &mut Context) -> Result<Option<u8>, ApiError> {
+        Ok(self.config(ctx)?.led_strip_brightness)
+    }
+
+    /// Require charging to be started remotely
+    pub fn set_remote_start_required(
+        &self,
+        ctx: &mut Context,
+        required: bool,
+    ) -> Result<(), ApiError> {
+        self.update_settings(
+            ctx,
+            &ChargerSettings {
+                remote_start_required: Some(required),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn command(&self, ctx: &mut Context, command: &str) -> Result<Option<CommandReply>, ApiError> {
+        ctx.charger_command(&self.id, command)
+    }
+
+    pub fn start(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        self.command(ctx, "start_charging")?;
+        Ok(())
+    }
+
+    pub fn pause(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        self.command(ctx, "pause_charging")?;
+        Ok(())
+    }
+
+    pub fn resume(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        self.command(ctx, "resume_charging")?;
+        Ok(())
+    }
+
+    pub fn stop(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        self.command(ctx, "stop_charging")?;
+        Ok(())
+    }
+
+    /// Grant charging when authorization is required, optionally on behalf
+    /// of a registered key
+    pub fn authorize(&self, ctx: &mut Context, token_id: Option<&str>) -> Result<(), ApiError> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params<'t> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            token_id: Option<&'t str>,
+        }
+
+        let _: Option<CommandReply> = ctx.post(
+            &format!("chargers/{}/commands/authorize", self.id),
+            &Params { token_id },
+        )?;
+        Ok(())
+    }
+
+    /// Revoke the authorization of the current charging
+    pub fn deauthorize(&self, ctx: &mut Context) -> Result<(), ApiError> {
+        self.command(ctx, "deauthorize")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use chrono::TimeZone;
+
+    use serde_json::json;
+
+    use super::{
+        ApiError, Charger, ChargerOpMode, ChargingSession, Circuit, CircuitId, Context,
+        EnergyUsage, FirmwareStatus, NaiveDateTime, OutputPhase, Page, Phase, Records, SetCurrent,
+        SiteId, TokenParseError, Triphase, UtcDateTime,
+    };
+    use crate::units::Amperes;
+    #[test]
+    fn token_save() {
+        let ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(1234),
+        );
 
         let saved = ctx.save();
-        let ctx2 = Context::from_saved(&saved).unwrap();
+        let ctx2 = Context::from_saved(saved.expose()).unwrap();
 
         assert_eq!(&ctx.auth_header, &ctx2.auth_header);
         assert_eq!(&ctx.refresh_token, &ctx2.refresh_token);
         assert!((ctx.token_expiration - ctx2.token_expiration) < Duration::from_secs(5))
     }
+
+    #[test]
+    fn shared_context_refresh() {
+        let ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(10),
+        );
+        let shared = crate::shared::SharedContext::new(ctx);
+        let mut fork = shared.context().unwrap();
+        assert!(fork.refresh_token.expose().is_empty());
+
+        // Tokens rotated by another context are picked up without a request
+        shared.with(|ctx| {
+            ctx.auth_header = "Bearer bbbbbbb0".into();
+            ctx.token_expiration = Instant::now() + Duration::from_secs(3600);
+        });
+        fork.refresh_token().unwrap();
+        assert_eq!(fork.auth_token(), "bbbbbbb0");
+        assert!(fork.token_expires_in() > Duration::from_secs(3000));
+    }
+
+    /// Answer requests with `responses`, one per connection, returning the
+    /// base URL to reach it, and the request lines and bodies once done
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/api/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for body in responses {
+                let (sock, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(sock);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = v.trim().parse().unwrap();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut content = vec![0; length];
+                reader.read_exact(&mut content).unwrap();
+                requests.push(format!(
+                    "{} {}",
+                    request.trim().trim_end_matches(" HTTP/1.1"),
+                    String::from_utf8(content).unwrap()
+                ));
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (base, server)
+    }
+
+    fn circuit() -> Circuit {
+        Circuit {
+            id: CircuitId(7),
+            uuid: String::new(),
+            site_id: SiteId(42),
+            circuit_panel_id: 1,
+            panel_name: "1".to_owned(),
+            rated_current: 32.0,
+            fuse: 32.0,
+            chargers: vec![],
+            use_dynamic_master: false,
+        }
+    }
+
+    #[test]
+    fn circuit_pause_restore() {
+        let current = r#"{"phase1": 16, "phase2": 16, "phase3": 10}"#;
+        let (base, server) = serve(vec![current, "", "", ""]);
+        let mut ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(3600),
+        )
+        .config(crate::config::ClientConfig::default().api_base(&base));
+        let circuit = circuit();
+
+        circuit.pause(&mut ctx).unwrap();
+        let saved = Triphase::new(Amperes(16.0), Amperes(16.0), Amperes(10.0));
+        assert_eq!(ctx.paused_current(circuit.id), Some(saved));
+
+        // Pausing again keeps the current read the first time
+        circuit.pause(&mut ctx).unwrap();
+        assert_eq!(ctx.paused_current(circuit.id), Some(saved));
+
+        assert!(circuit.restore(&mut ctx).unwrap());
+        assert_eq!(ctx.paused_current(circuit.id), None);
+        assert!(!circuit.restore(&mut ctx).unwrap());
+
+        let path = "/api/sites/42/circuits/7/dynamicCurrent";
+        let zero = r#"{"phase1":0.0,"phase2":0.0,"phase3":0.0}"#;
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                format!("GET {path} "),
+                format!("POST {path} {zero}"),
+                format!("POST {path} {zero}"),
+                format!(r#"POST {path} {{"phase1":16.0,"phase2":16.0,"phase3":10.0}}"#),
+            ]
+        );
+    }
+
+    #[test]
+    fn circuit_pause_without_current() {
+        let current = r#"{"phase1": 0, "phase2": 0, "phase3": 0}"#;
+        let (base, server) = serve(vec![current, ""]);
+        let mut ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(3600),
+        )
+        .config(crate::config::ClientConfig::default().api_base(&base));
+        let circuit = circuit();
+
+        circuit.pause(&mut ctx).unwrap();
+        assert_eq!(ctx.paused_current(circuit.id), None);
+        assert!(!circuit.restore(&mut ctx).unwrap());
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "debug-bodies")]
+    #[test]
+    fn captured_post_bodies() {
+        use std::sync::{Arc, Mutex};
+
+        let (base, server) = serve(vec![r#"{"accessToken":"xyz","userId":1}"#]);
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let mut ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(3600),
+        )
+        .config(crate::config::ClientConfig::default().api_base(&base))
+        .capture_bodies(
+            |path| path == "accounts/refresh_token",
+            move |c| {
+                sink.lock().unwrap().push((
+                    c.path.to_owned(),
+                    c.request.cloned(),
+                    c.response.clone(),
+                ))
+            },
+        );
+        let _: serde_json::Value = ctx
+            .post(
+                "accounts/refresh_token",
+                &json!({"refreshToken": "abc", "user": "me"}),
+            )
+            .unwrap();
+        server.join().unwrap();
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        let (path, request, response) = &captured[0];
+        assert_eq!(path, "accounts/refresh_token");
+        assert_eq!(
+            request.as_ref().unwrap(),
+            &json!({"refreshToken": "<secret>", "user": "me"})
+        );
+        assert_eq!(response, &json!({"accessToken": "<secret>", "userId": 1}));
+    }
+
+    #[test]
+    fn pyeasee_token() {
+        let ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(1234),
+        );
+
+        let saved = ctx.save_pyeasee();
+        let ctx2 = Context::from_pyeasee(saved.expose()).unwrap();
+        assert_eq!(&ctx.auth_header, &ctx2.auth_header);
+        assert_eq!(&ctx.refresh_token, &ctx2.refresh_token);
+        assert!((ctx.token_expiration - ctx2.token_expiration) < Duration::from_secs(5));
+
+        let iso = r#"{"accessToken": "a", "refreshToken": "r", "expires": "2001-01-01T00:00:00"}"#;
+        let ctx3 = Context::from_pyeasee(iso).unwrap();
+        assert!(ctx3.token_expiration <= Instant::now());
+
+        let corrupt = r#"{"accessToken": "a", "refreshToken": "r", "expires": 1e20}"#;
+        assert!(matches!(
+            Context::from_pyeasee(corrupt),
+            Err(TokenParseError::InvalidExpiration(_))
+        ));
+    }
+
+    #[test]
+    fn token_clock_skew() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        // Saved by a clock one year ahead of the current one
+        let skewed = format!("aaa\nbbb\n{}\n{}\n", now + 86400 * 366, now + 86400 * 365);
+        let ctx = Context::from_saved(&skewed).unwrap();
+        assert!(ctx.token_expiration <= Instant::now());
+
+        let sane = format!("aaa\nbbb\n{}\n{}\n", now + 3600, now - 3600);
+        let ctx = Context::from_saved(&sane).unwrap();
+        assert!(ctx.token_expiration > Instant::now() + Duration::from_secs(3500));
+    }
+
+    #[test]
+    fn url_datetime_format() {
+        let dt = UtcDateTime(chrono::Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 5).unwrap());
+        assert_eq!(dt.to_url_param(), "2024-03-01T12:30:05Z");
+    }
+
+    #[test]
+    fn server_error_body() {
+        let body =
+            r#"{"errorCode":100,"errorCodeName":"InvalidRequest","title":"Invalid request"}"#;
+        let resp = ureq::Response::new(409, "Conflict", body).unwrap();
+        let err = ApiError::from(ureq::Error::Status(409, resp));
+        assert_eq!(err.status(), Some(409));
+        assert_eq!(err.code(), "api.http_status");
+        match err {
+            ApiError::Server {
+                code, code_name, ..
+            } => {
+                assert_eq!(code, Some(100));
+                assert_eq!(code_name.as_deref(), Some("InvalidRequest"));
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn page_formats() {
+        let parse = |v| match serde_json::from_value::<Page<u32>>(v).unwrap() {
+            Page::List(items) | Page::Wrapped { data: items } => items,
+        };
+        assert_eq!(parse(json!([1, 2])), [1, 2]);
+        assert_eq!(parse(json!({"data": [3], "total": 1})), [3]);
+        assert!(serde_json::from_value::<Page<u32>>(json!({"items": [1]})).is_err());
+    }
+
+    #[test]
+    fn paged_termination() {
+        let mut ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(3600),
+        );
+        let mut collect = |pages: Vec<serde_json::Value>| {
+            let mut paged = ctx.paged::<u32>("sessions", 2);
+            let mut requested = vec![];
+            let mut items = vec![];
+            while let Some(item) = paged.next_with(|_, path| {
+                requested.push(path.to_owned());
+                let page = pages.get(requested.len() - 1).cloned();
+                Ok(page.map(|p| serde_json::from_value(p).unwrap()))
+            }) {
+                items.push(item.unwrap());
+            }
+            (items, requested.len())
+        };
+
+        // A short page is the last one
+        assert_eq!(
+            collect(vec![json!([1, 2]), json!({"data": [3]})]),
+            (vec![1, 2, 3], 2)
+        );
+        // So is an empty one, or a missing one
+        assert_eq!(collect(vec![json!([1, 2]), json!([])]), (vec![1, 2], 2));
+        assert_eq!(collect(vec![json!([1, 2])]), (vec![1, 2], 2));
+        // The page number is ignored, and the first page sent again
+        assert_eq!(collect(vec![json!([1, 2]); 5]), (vec![1, 2], 2));
+    }
+
+    #[test]
+    fn records_stream() {
+        let body = r#" [ {"date": "2024-05-01T10:00:00Z", "totalEnergy": 1.5},
+            {"date": "2024-05-01T11:00:00Z", "totalEnergy": 2.25} ] "#;
+        let records = Records::<EnergyUsage>::new(Box::new(io::Cursor::new(body)));
+        let usage: Vec<f64> = records.map(|r| r.unwrap().energy_kwh.0).collect();
+        assert_eq!(usage, [1.5, 2.25]);
+
+        let empty = Records::<EnergyUsage>::new(Box::new(io::Cursor::new("")));
+        assert_eq!(empty.count(), 0);
+
+        let mut truncated = Records::<EnergyUsage>::new(Box::new(io::Cursor::new(r#"[{"date": "#)));
+        assert!(truncated.next().unwrap().is_err());
+        assert!(truncated.next().is_none());
+    }
+
+    #[test]
+    fn datetime_formats() {
+        let utc = |s: &str| serde_json::from_value::<UtcDateTime>(s.into()).unwrap();
+        let expected = utc("2024-05-01T10:00:00Z");
+        assert_eq!(utc("2024-05-01T12:00:00+02:00"), expected);
+        assert_eq!(utc("2024-05-01T10:00:00.000"), expected);
+        assert_eq!(utc("2024-05-01 10:00:00"), expected);
+        assert_eq!(utc("2024-05-01T10:00"), expected);
+        assert_eq!(
+            serde_json::from_str::<UtcDateTime>(r#""2024-05-01T10:00:00\u005a""#).unwrap(),
+            expected
+        );
+
+        let naive = |s: &str| serde_json::from_value::<NaiveDateTime>(s.into()).unwrap();
+        let local = naive("2024-05-01T12:00:00.5");
+        assert_eq!(naive("2024-05-01T12:00:00.500+02:00"), local);
+        assert_eq!(
+            serde_json::to_value(local).unwrap(),
+            "2024-05-01T12:00:00.500"
+        );
+        assert!(serde_json::from_value::<UtcDateTime>("yesterday".into()).is_err());
+
+        let session: super::ChargingSession = serde_json::from_value(json!({
+            "sessionEnergy": 5.0,
+            "sessionStart": "2024-05-01T12:00:00+02:00",
+            "sessionStop": null,
+            "firstEnergyTransferPeriodStart": "",
+        }))
+        .unwrap();
+        assert_eq!(session.session_start, Some(expected));
+        assert_eq!(session.session_stop, None);
+        assert_eq!(session.first_energy_transfer_period_start, None);
+        assert_eq!(session.last_energy_transfer_period_end, None);
+    }
+
+    #[test]
+    fn models_roundtrip() {
+        let charger = json!({
+            "id": "EH123456",
+            "name": "Garage",
+            "productCode": 1,
+            "color": null,
+            "createdOn": "2024-05-01T10:00:00",
+            "updatedOn": "2024-05-01T10:00:00",
+            "levelOfAccess": 7,
+        });
+        let parsed: Charger = serde_json::from_value(charger.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), charger);
+
+        let firmware = json!({"currentVersion": 300, "latestVersion": 302});
+        let parsed: FirmwareStatus = serde_json::from_value(firmware.clone()).unwrap();
+        assert!(parsed.update_available);
+        assert_eq!(serde_json::to_value(parsed).unwrap(), firmware);
+
+        let site: super::Site = serde_json::from_value(json!({
+            "id": 42,
+            "levelOfAccess": 1,
+            "address": {
+                "street": "Storgata",
+                "zip": "0155",
+                "area": "Oslo",
+                "country": {"id": "NO", "name": "Norway", "phonePrefix": 47},
+                "latitude": 59.91,
+                "longitude": 10.75,
+            },
+        }))
+        .unwrap();
+        let address = site.address.unwrap();
+        assert_eq!(address.city.as_deref(), Some("Oslo"));
+        assert_eq!(address.coordinates(), Some((59.91, 10.75)));
+
+        let mut elsewhere = address.clone();
+        elsewhere.latitude = Some(super::Coordinate(f64::NAN));
+        assert_eq!(elsewhere, elsewhere.clone());
+        assert!(address < elsewhere);
+    }
+
+    #[test]
+    fn triphase_arithmetic() {
+        let a = Triphase::new(Amperes(10.0), Amperes(12.0), Amperes(8.0));
+        let b = Triphase::from(Amperes(2.0));
+        assert_eq!(
+            a - b,
+            Triphase::new(Amperes(8.0), Amperes(10.0), Amperes(6.0))
+        );
+        assert_eq!((a + b)[Phase::L2], Amperes(14.0));
+        assert_eq!(a.get(Phase::L3), Amperes(8.0));
+        assert_eq!(a.max(), Amperes(12.0));
+        assert_eq!(a.min(), Amperes(8.0));
+        assert_eq!(a.sum(), Amperes(30.0));
+        assert!(a.is_balanced(Amperes(4.0)));
+        assert!(!a.is_balanced(Amperes(3.0)));
+    }
+
+    #[test]
+    fn set_current_builder() {
+        let current = SetCurrent::builder()
+            .all(Amperes(16.0))
+            .phase3(Amperes(10.0))
+            .time_to_live(Duration::from_secs(90))
+            .build();
+        assert_eq!(
+            serde_json::to_value(current).unwrap(),
+            json!({"timeToLive": 2, "phase1": 16.0, "phase2": 16.0, "phase3": 10.0})
+        );
+        assert!(current.validate(Amperes(16.0)).is_ok());
+        assert!(matches!(
+            current.validate(Amperes(12.0)),
+            Err(ApiError::InvalidCurrent { current, .. }) if current == Amperes(16.0)
+        ));
+        let negative = SetCurrent::builder().phase1(Amperes(-1.0)).build();
+        assert!(negative.validate(Amperes(32.0)).is_err());
+    }
+
+    #[test]
+    fn display_formats() {
+        assert_eq!(
+            ChargerOpMode::AwaitingAuthentication.to_string(),
+            "Awaiting authentication"
+        );
+        assert_eq!(ChargerOpMode::Unknown(0).to_string(), "Mode 0");
+        assert_eq!(OutputPhase::L1L3ToL2.to_string(), "L1L3-L2");
+
+        let session: ChargingSession = serde_json::from_value(json!({
+            "chargerId": "EH123456",
+            "sessionEnergy": 4.25,
+            "sessionStart": "2024-05-01T10:00:00Z",
+            "costIncludingVat": 2.35,
+            "currencyId": "NOK",
+        }))
+        .unwrap();
+        assert_eq!(
+            session.to_string(),
+            "EH123456: 4.25 kWh from 2024-05-01 10:00:00 UTC, 2.35 NOK"
+        );
+    }
+
+    #[test]
+    fn unknown_enum_values() {
+        let mode: ChargerOpMode = serde_json::from_value(json!(42)).unwrap();
+        assert_eq!(mode, ChargerOpMode::Unknown(42));
+        assert_eq!(serde_json::to_value(mode).unwrap(), json!(42));
+        let phase: OutputPhase = serde_json::from_value(json!(30)).unwrap();
+        assert_eq!(phase, OutputPhase::L1L2L3ToN);
+        assert_eq!(phase.phase_count(), Some(3));
+        assert_eq!(OutputPhase::Unknown(30).phase_count(), None);
+    }
 }
@@ -0,0 +1,95 @@
+use std::{sync::mpsc, time::Duration};
+
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::{
+    api::{ApiError, Context},
+    installation::Installation,
+    observation::{Event, ObservationError, Stream},
+    shutdown::Shutdown,
+    stream::NegotiateError,
+};
+
+/// Longest delay between two checks of the shutdown signal by the stream thread
+const SHUTDOWN_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum BootstrapError {
+    #[error("API error: {0}")]
+    Api(#[from] ApiError),
+
+    #[error("stream: {0}")]
+    Negotiate(#[from] NegotiateError),
+
+    #[error("subscription: {0}")]
+    Subscribe(#[source] Box<tungstenite::Error>),
+
+    #[error("could not configure stream socket: {0}")]
+    Socket(#[source] std::io::Error),
+
+    #[error("could not start stream thread: {0}")]
+    Thread(#[from] std::io::Error),
+}
+
+impl From<tungstenite::Error> for BootstrapError {
+    fn from(e: tungstenite::Error) -> Self {
+        BootstrapError::Subscribe(Box::new(e))
+    }
+}
+
+/// Everything needed to start working with the API
+pub struct Connection {
+    pub context: Context,
+    pub installation: Installation,
+
+    /// Events of all the chargers of the installation. The stream is read by
+    /// a background thread, which stops after the first error, when the
+    /// receiver is dropped, or when shutdown is triggered.
+    pub events: mpsc::Receiver<Result<Event, ObservationError>>,
+}
+
+/// Log in, fetch the installation topology, and subscribe to the events of
+/// all the chargers. The stream thread is registered with `shutdown`, which
+/// closes the connection when triggered.
+pub fn bootstrap(
+    user: &str,
+    password: &str,
+    shutdown: &Shutdown,
+) -> Result<Connection, BootstrapError> {
+    let mut context = Context::from_login(user, password)?;
+    let installation = Installation::load(&mut context)?;
+
+    let mut stream = Stream::from_context(&mut context)?;
+    for charger in installation.chargers() {
+        debug!("Subscribing to {}", charger.id);
+        stream.subscribe(&charger.id)?;
+    }
+
+    // Wake up regularly to notice a shutdown on a quiet stream
+    stream
+        .set_read_timeout(Some(SHUTDOWN_POLL))
+        .map_err(BootstrapError::Socket)?;
+
+    let (tx, events) = mpsc::channel();
+    shutdown.spawn("easee-stream", move |shutdown| {
+        while !shutdown.is_triggered() {
+            let event = match stream.recv() {
+                Err(e) if e.is_timeout() => continue,
+                event => event,
+            };
+            let failed = event.is_err();
+            if tx.send(event).is_err() || failed {
+                break;
+            }
+        }
+        info!("Stopping stream thread");
+        let _ = stream.close();
+    })?;
+
+    Ok(Connection {
+        context,
+        installation,
+        events,
+    })
+}
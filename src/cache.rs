@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::api::Context;
+use crate::schema::endpoint_template;
+
+/// Responses of slow-changing endpoints, kept for a time-to-live set per
+/// endpoint. Endpoints without a TTL are never cached.
+#[derive(Default)]
+pub(crate) struct TtlCache {
+    ttls: HashMap<String, Duration>,
+    entries: HashMap<String, (Instant, Value)>,
+}
+
+impl TtlCache {
+    pub(crate) fn set_ttl(&mut self, endpoint: &str, ttl: Duration) {
+        self.ttls.insert(endpoint.to_owned(), ttl);
+    }
+
+    pub(crate) fn get(&self, path: &str, now: Instant) -> Option<Value> {
+        let ttl = self.ttls.get(&endpoint_template(path))?;
+        let (stored, value) = self.entries.get(path)?;
+        (now.duration_since(*stored) < *ttl).then(|| value.clone())
+    }
+
+    pub(crate) fn insert(&mut self, path: &str, value: &Value, now: Instant) {
+        if self.ttls.contains_key(&endpoint_template(path)) {
+            self.entries.insert(path.to_owned(), (now, value.clone()));
+        }
+    }
+
+    /// Forget all responses, after a change made through the API
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// A [`Context`] memoizing the responses of slow-changing endpoints, such as
+/// the sites and charger configurations, for dashboards polling often. States
+/// and commands always go through. Any change made through the context, such
+/// as a command or a settings update, drops all cached responses.
+///
+/// Use it wherever a `&mut Context` is expected.
+pub struct CachedContext {
+    ctx: Context,
+}
+
+impl CachedContext {
+    /// Cache sites and chargers for 10 minutes, and configurations for a minute
+    pub fn new(ctx: Context) -> Self {
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        Self { ctx }
+            .ttl("sites", minutes(10))
+            .ttl("sites/{}", minutes(10))
+            .ttl("sites/{}/circuits/{}/settings", minutes(1))
+            .ttl("chargers", minutes(10))
+            .ttl("chargers/{}", minutes(10))
+            .ttl("chargers/{}/config", minutes(1))
+            .ttl("chargers/{}/site", minutes(10))
+    }
+
+    /// Cache the responses of `endpoint`, such as `chargers/{}/config`, for
+    /// `ttl`. A zero TTL disables caching.
+    pub fn ttl(mut self, endpoint: &str, ttl: Duration) -> Self {
+        self.ctx.ttl_cache().set_ttl(endpoint, ttl);
+        self
+    }
+
+    pub fn into_inner(mut self) -> Context {
+        self.ctx.disable_ttl_cache();
+        self.ctx
+    }
+}
+
+impl Deref for CachedContext {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        &self.ctx
+    }
+}
+
+impl DerefMut for CachedContext {
+    fn deref_mut(&mut self) -> &mut Context {
+        &mut self.ctx
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use super::TtlCache;
+
+    #[test]
+    fn per_endpoint_ttl() {
+        let mut cache = TtlCache::default();
+        cache.set_ttl("chargers/{}/config", Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.insert("chargers/EH1/config", &json!({"a": 1}), now);
+        cache.insert("chargers/EH1/state", &json!({"b": 2}), now);
+        assert_eq!(cache.get("chargers/EH1/config", now), Some(json!({"a": 1})));
+        assert_eq!(cache.get("chargers/EH1/state", now), None);
+        assert_eq!(
+            cache.get("chargers/EH1/config", now + Duration::from_secs(61)),
+            None
+        );
+
+        cache.clear();
+        assert_eq!(cache.get("chargers/EH1/config", now), None);
+    }
+}
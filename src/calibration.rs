@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use crate::api::ChargerState;
+use crate::ids::ChargerId;
+use crate::units::{Amperes, Kilowatts};
+
+/// Linear correction of a measurement: `value * scale + offset`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Correction {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Correction {
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+}
+
+/// Per-charger corrections for power and current measurements, compensating
+/// for the metering bias of some hardware revisions. Apply them to states and
+/// events before handing them over to controllers.
+#[derive(Clone, Debug, Default)]
+pub struct Calibration {
+    power: HashMap<ChargerId, Correction>,
+    current: HashMap<ChargerId, Correction>,
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Correction for power measurements of a charger, in kW
+    pub fn set_power(&mut self, charger: &ChargerId, correction: Correction) {
+        self.power.insert(charger.clone(), correction);
+    }
+
+    /// Correction for current measurements of a charger, in A
+    pub fn set_current(&mut self, charger: &ChargerId, correction: Correction) {
+        self.current.insert(charger.clone(), correction);
+    }
+
+    pub fn power(&self, charger: &str, kw: f64) -> f64 {
+        self.power.get(charger).map_or(kw, |c| c.apply(kw))
+    }
+
+    pub fn current(&self, charger: &str, amperes: f64) -> f64 {
+        self.current
+            .get(charger)
+            .map_or(amperes, |c| c.apply(amperes))
+    }
+
+    /// Correct the measurements of a state read from the REST API
+    pub fn apply_state(&self, charger: &str, state: &mut ChargerState) {
+        for power in [&mut state.total_power, &mut state.energy_per_hour] {
+            *power = Kilowatts(self.power(charger, power.0));
+        }
+        for current in [
+            Some(&mut state.output_current),
+            Some(&mut state.circuit_total_phase_conductor_current_l1),
+            Some(&mut state.circuit_total_phase_conductor_current_l2),
+            Some(&mut state.circuit_total_phase_conductor_current_l3),
+            state.in_current_t2.as_mut(),
+            state.in_current_t3.as_mut(),
+            state.in_current_t4.as_mut(),
+            state.in_current_t5.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *current = Amperes(self.current(charger, current.0));
+        }
+    }
+
+    /// Correct the measurement carried by an observation event
+    pub fn apply_event(&self, event: &mut crate::observation::Event) {
+        use crate::observation::Observation;
+        match &mut event.observation {
+            Observation::TotalPower(kw) | Observation::EnergyPerHour(kw) => {
+                *kw = self.power(&event.charger, *kw)
+            }
+            Observation::IntCurrent {
+                current: amperes, ..
+            }
+            | Observation::CircuitTotalCurrent { amperes, .. } => {
+                *amperes = self.current(&event.charger, *amperes)
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Calibration, Correction};
+
+    fn calibration() -> Calibration {
+        let mut calibration = Calibration::new();
+        let charger = "EH000001".parse().unwrap();
+        calibration.set_power(
+            &charger,
+            Correction {
+                scale: 0.5,
+                offset: 0.0,
+            },
+        );
+        calibration.set_current(
+            &charger,
+            Correction {
+                scale: 1.0,
+                offset: -1.0,
+            },
+        );
+        calibration
+    }
+
+    #[test]
+    fn corrections() {
+        let calibration = calibration();
+        assert_eq!(calibration.power("EH000001", 7.0), 3.5);
+        assert_eq!(calibration.current("EH000001", 16.0), 15.0);
+        // Chargers without corrections are left alone
+        assert_eq!(calibration.power("EH000002", 7.0), 7.0);
+        assert_eq!(calibration.current("EH000002", 16.0), 16.0);
+    }
+
+    #[test]
+    fn corrected_events() {
+        use crate::observation::{Event, InputPin, Observation};
+
+        let calibration = calibration();
+        let corrected = |observation| {
+            let mut event = Event {
+                charger: "EH000001".parse().unwrap(),
+                observation,
+            };
+            calibration.apply_event(&mut event);
+            event.observation
+        };
+        assert!(matches!(
+            corrected(Observation::TotalPower(7.0)),
+            Observation::TotalPower(kw) if kw == 3.5
+        ));
+        assert!(matches!(
+            corrected(Observation::EnergyPerHour(7.0)),
+            Observation::EnergyPerHour(kw) if kw == 3.5
+        ));
+        assert!(matches!(
+            corrected(Observation::IntCurrent {
+                pin: InputPin::T2,
+                current: 16.0
+            }),
+            Observation::IntCurrent { current, .. } if current == 15.0
+        ));
+        assert!(matches!(
+            corrected(Observation::CircuitTotalCurrent {
+                phase: 1,
+                amperes: 16.0
+            }),
+            Observation::CircuitTotalCurrent { phase: 1, amperes } if amperes == 15.0
+        ));
+    }
+}
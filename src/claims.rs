@@ -0,0 +1,97 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::DateTime;
+use serde_json::Value;
+
+use crate::api::{TokenParseError, UtcDateTime};
+
+/// Claims of an access token, as issued by Easee. They are decoded without
+/// checking the signature, so only trust them for tokens received from the API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenClaims {
+    pub account_id: Option<u32>,
+    pub user_id: Option<u32>,
+    pub name: Option<String>,
+    pub roles: Vec<String>,
+    pub issued_at: Option<UtcDateTime>,
+    pub expires_at: Option<UtcDateTime>,
+}
+
+/// Numeric claims are sent either as numbers or as strings
+fn number(claims: &Value, key: &str) -> Option<i64> {
+    match claims.get(key)? {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn timestamp(claims: &Value, key: &str) -> Option<UtcDateTime> {
+    DateTime::from_timestamp(number(claims, key)?, 0).map(UtcDateTime)
+}
+
+/// Decode the payload of a JWT, without verification
+pub(crate) fn decode(token: &str) -> Result<TokenClaims, TokenParseError> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or(TokenParseError::MalformedJwt)?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| TokenParseError::MalformedJwt)?;
+    let claims: Value = serde_json::from_slice(&payload)?;
+
+    let roles = match claims.get("role") {
+        Some(Value::String(role)) => vec![role.clone()],
+        Some(Value::Array(roles)) => roles
+            .iter()
+            .filter_map(|r| r.as_str().map(str::to_owned))
+            .collect(),
+        _ => vec![],
+    };
+
+    Ok(TokenClaims {
+        account_id: number(&claims, "AccountId").and_then(|id| id.try_into().ok()),
+        user_id: number(&claims, "UserId").and_then(|id| id.try_into().ok()),
+        name: claims
+            .get("unique_name")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        roles,
+        issued_at: timestamp(&claims, "iat"),
+        expires_at: timestamp(&claims, "exp"),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde_json::json;
+
+    use super::decode;
+
+    #[test]
+    fn easee_claims() {
+        let payload = json!({
+            "AccountId": "1234",
+            "UserId": 5678,
+            "unique_name": "Jane Doe",
+            "role": ["User"],
+            "iat": 1714564800,
+            "exp": 1714568400,
+        });
+        let token = format!(
+            "eyJhbGciOiJIUzI1NiJ9.{}.c2lnbmF0dXJl",
+            URL_SAFE_NO_PAD.encode(payload.to_string())
+        );
+
+        let claims = decode(&token).unwrap();
+        assert_eq!(claims.account_id, Some(1234));
+        assert_eq!(claims.user_id, Some(5678));
+        assert_eq!(claims.roles, ["User"]);
+        assert_eq!(
+            claims.expires_at.unwrap().0.to_rfc3339(),
+            "2024-05-01T13:00:00+00:00"
+        );
+        assert!(decode("not a token").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+/// Quality of the link to the chargers and the API
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    Normal,
+    Degraded,
+}
+
+/// Adapts the polling cadence to the connectivity, for chargers on a
+/// cellular backhaul or sites with a flaky uplink.
+///
+/// Connectivity is considered degraded when too many recent API calls failed,
+/// or when the observation stream went silent. While degraded, the poll
+/// interval doubles at every poll up to `max_interval`, and non-critical
+/// writes should be skipped. Once connectivity is back, the interval halves
+/// at every poll down to `base_interval`.
+#[derive(Clone, Debug)]
+pub struct AdaptivePolling {
+    pub base_interval: Duration,
+    pub max_interval: Duration,
+
+    /// Fraction of failed calls, among the last `window`, above which
+    /// connectivity is degraded
+    pub error_threshold: f64,
+    pub window: usize,
+
+    /// Silence of the stream after which connectivity is degraded
+    pub stream_timeout: Duration,
+
+    results: VecDeque<bool>,
+    last_stream_event: Option<Instant>,
+    interval: Duration,
+    state: Connectivity,
+}
+
+impl AdaptivePolling {
+    pub fn new(base_interval: Duration) -> Self {
+        Self {
+            base_interval,
+            max_interval: base_interval * 16,
+            error_threshold: 0.3,
+            window: 20,
+            stream_timeout: Duration::from_secs(300),
+            results: VecDeque::new(),
+            last_stream_event: None,
+            interval: base_interval,
+            state: Connectivity::Normal,
+        }
+    }
+
+    /// Record the outcome of an API call
+    pub fn record<T, E>(&mut self, result: &Result<T, E>) {
+        self.results.push_back(result.is_ok());
+        while self.results.len() > self.window {
+            self.results.pop_front();
+        }
+    }
+
+    /// Record that the observation stream delivered an event
+    pub fn stream_event(&mut self, now: Instant) {
+        self.last_stream_event = Some(now);
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        let errors = self.results.iter().filter(|ok| !**ok).count();
+        errors as f64 / self.results.len() as f64
+    }
+
+    pub fn connectivity(&self, now: Instant) -> Connectivity {
+        let stream_silent = self
+            .last_stream_event
+            .is_some_and(|last| now.duration_since(last) > self.stream_timeout);
+        if stream_silent || self.error_rate() > self.error_threshold {
+            Connectivity::Degraded
+        } else {
+            Connectivity::Normal
+        }
+    }
+
+    /// Interval to wait before the next poll. Call once per poll.
+    pub fn next_interval(&mut self, now: Instant) -> Duration {
+        let state = self.connectivity(now);
+        if state != self.state {
+            match state {
+                Connectivity::Degraded => warn!("Connectivity degraded, slowing down polling"),
+                Connectivity::Normal => info!("Connectivity restored"),
+            }
+            self.state = state;
+        }
+        self.interval = match state {
+            Connectivity::Degraded => (self.interval * 2).min(self.max_interval),
+            Connectivity::Normal => (self.interval / 2).max(self.base_interval),
+        };
+        self.interval
+    }
+
+    /// Whether a write should be sent now. Critical writes always are, others
+    /// are suspended while connectivity is degraded.
+    pub fn allow_write(&self, critical: bool, now: Instant) -> bool {
+        critical || self.connectivity(now) == Connectivity::Normal
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{AdaptivePolling, Connectivity};
+
+    #[test]
+    fn backoff_and_restore() {
+        let now = Instant::now();
+        let mut polling = AdaptivePolling::new(Duration::from_secs(10));
+        polling.window = 4;
+        polling.stream_event(now);
+
+        for _ in 0..2 {
+            polling.record(&Err::<(), ()>(()));
+        }
+        assert_eq!(polling.connectivity(now), Connectivity::Degraded);
+        assert!(!polling.allow_write(false, now));
+        assert!(polling.allow_write(true, now));
+        assert_eq!(polling.next_interval(now), Duration::from_secs(20));
+        assert_eq!(polling.next_interval(now), Duration::from_secs(40));
+
+        for _ in 0..4 {
+            polling.record(&Ok::<(), ()>(()));
+        }
+        assert_eq!(polling.next_interval(now), Duration::from_secs(20));
+        assert_eq!(polling.next_interval(now), Duration::from_secs(10));
+        assert_eq!(polling.next_interval(now), Duration::from_secs(10));
+
+        let later = now + Duration::from_secs(301);
+        assert_eq!(polling.connectivity(later), Connectivity::Degraded);
+    }
+}
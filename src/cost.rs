@@ -0,0 +1,308 @@
+use chrono::{FixedOffset, NaiveTime, Timelike};
+
+use crate::api::{ChargingSession, EnergyUsage, UtcDateTime};
+use crate::units::KilowattHours;
+
+/// Grid tariff applying to the energy consumed above `from_kwh` within a
+/// billing period
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridTier {
+    pub from_kwh: f64,
+    pub price_per_kwh: f64,
+}
+
+/// Fees charged by the utility on top of the energy itself. All amounts are
+/// in the currency of the sessions, VAT included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeSchedule {
+    /// Fixed fee per billing period (month)
+    pub monthly_fee: f64,
+
+    /// Fixed fee per charging session
+    pub session_fee: f64,
+
+    /// Tiered grid tariff, applied to the total energy of the billing period
+    pub grid_tiers: Vec<GridTier>,
+}
+
+/// Cost of the charging over a billing period, by component
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CostBreakdown {
+    pub energy: f64,
+    pub grid: f64,
+    pub session_fees: f64,
+    pub fixed_fees: f64,
+}
+
+impl CostBreakdown {
+    pub fn total(&self) -> f64 {
+        self.energy + self.grid + self.session_fees + self.fixed_fees
+    }
+
+    /// Total as an exact decimal, summing the exact value of each component,
+    /// see [`crate::money::exact`]. `None` if a component is not finite.
+    #[cfg(feature = "decimal")]
+    pub fn exact_total(&self) -> Option<rust_decimal::Decimal> {
+        [self.energy, self.grid, self.session_fees, self.fixed_fees]
+            .into_iter()
+            .map(crate::money::exact)
+            .sum()
+    }
+}
+
+/// Energy cost of a session, as reported by the API
+fn energy_cost(session: &ChargingSession) -> f64 {
+    session
+        .cost_including_vat
+        .or_else(|| Some(session.session_energy.0 * session.price_per_kwh_including_vat?))
+        .unwrap_or(0.0)
+}
+
+impl FeeSchedule {
+    pub fn with_monthly_fee(mut self, fee: f64) -> Self {
+        self.monthly_fee = fee;
+        self
+    }
+
+    pub fn with_session_fee(mut self, fee: f64) -> Self {
+        self.session_fee = fee;
+        self
+    }
+
+    /// Add a grid tariff tier, starting at `from_kwh`
+    pub fn with_grid_tier(mut self, from_kwh: f64, price_per_kwh: f64) -> Self {
+        self.grid_tiers.push(GridTier {
+            from_kwh,
+            price_per_kwh,
+        });
+        self.grid_tiers
+            .sort_by(|a, b| a.from_kwh.total_cmp(&b.from_kwh));
+        self
+    }
+
+    /// Grid cost of the energy consumed over a billing period
+    pub fn grid_cost(&self, kwh: f64) -> f64 {
+        let mut cost = 0.0;
+        for (i, tier) in self.grid_tiers.iter().enumerate() {
+            let end = self
+                .grid_tiers
+                .get(i + 1)
+                .map_or(f64::INFINITY, |next| next.from_kwh);
+            let energy = kwh.min(end) - tier.from_kwh;
+            if energy > 0.0 {
+                cost += energy * tier.price_per_kwh;
+            }
+        }
+        cost
+    }
+
+    /// Cost of the sessions of a billing period spanning `months` months.
+    /// Sessions without a price reported by the API count for no energy cost.
+    pub fn breakdown(&self, sessions: &[ChargingSession], months: u32) -> CostBreakdown {
+        let energy: KilowattHours = sessions.iter().map(|s| s.session_energy).sum();
+        CostBreakdown {
+            energy: sessions.iter().map(energy_cost).sum(),
+            grid: self.grid_cost(energy.0),
+            session_fees: self.session_fee * sessions.len() as f64,
+            fixed_fees: self.monthly_fee * months as f64,
+        }
+    }
+}
+
+/// Energy price applying between two times of the day, local to the tariff.
+/// A band may wrap around midnight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeBand {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub price_per_kwh: f64,
+}
+
+impl TimeBand {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Energy price, for sites where the API reports no cost. Prices are
+/// excluding VAT; the costs computed from the tariff include it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tariff {
+    /// Price outside of the time bands
+    pub price_per_kwh: f64,
+
+    /// Time-of-use prices, the first matching band applies
+    pub bands: Vec<TimeBand>,
+
+    pub vat_percentage: f64,
+
+    /// Offset of the local time the bands refer to. Daylight saving time is
+    /// not accounted for.
+    pub offset: FixedOffset,
+}
+
+impl Tariff {
+    /// A single price at all times
+    pub fn fixed(price_per_kwh: f64) -> Self {
+        Self {
+            price_per_kwh,
+            bands: vec![],
+            vat_percentage: 0.0,
+            offset: FixedOffset::east_opt(0).unwrap(),
+        }
+    }
+
+    pub fn with_band(mut self, start: NaiveTime, end: NaiveTime, price_per_kwh: f64) -> Self {
+        self.bands.push(TimeBand {
+            start,
+            end,
+            price_per_kwh,
+        });
+        self
+    }
+
+    pub fn with_vat(mut self, percentage: f64) -> Self {
+        self.vat_percentage = percentage;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: FixedOffset) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    fn local_time(&self, at: UtcDateTime) -> NaiveTime {
+        at.0.with_timezone(&self.offset).time()
+    }
+
+    /// Price of a kWh at the given time, excluding VAT
+    pub fn price_at(&self, at: UtcDateTime) -> f64 {
+        let time = self.local_time(at);
+        self.bands
+            .iter()
+            .find(|b| b.contains(time))
+            .map_or(self.price_per_kwh, |b| b.price_per_kwh)
+    }
+
+    /// Add the VAT to an amount
+    pub fn with_vat_applied(&self, amount: f64) -> f64 {
+        amount * (1.0 + self.vat_percentage / 100.0)
+    }
+
+    /// Time until the price may change, at most a day
+    fn until_next_edge(&self, at: UtcDateTime) -> chrono::Duration {
+        let day = 24 * 3600;
+        let now = self.local_time(at).num_seconds_from_midnight() as i64;
+        let next = self
+            .bands
+            .iter()
+            .flat_map(|b| [b.start, b.end])
+            .map(|edge| (edge.num_seconds_from_midnight() as i64 - now).rem_euclid(day))
+            .filter(|d| *d > 0)
+            .min()
+            .unwrap_or(day);
+        chrono::Duration::seconds(next)
+    }
+
+    /// Cost of the energy consumed in each time slot, priced at the start of the slot
+    pub fn usage_cost(&self, usage: &[EnergyUsage]) -> f64 {
+        let cost: f64 = usage
+            .iter()
+            .map(|u| u.energy_kwh.0 * self.price_at(u.date))
+            .sum();
+        self.with_vat_applied(cost)
+    }
+
+    /// Cost of a session started at `started`. The energy is assumed to be
+    /// consumed evenly over the charging duration.
+    pub fn session_cost(&self, session: &ChargingSession, started: UtcDateTime) -> f64 {
+        let total = session.charge_duration_in_seconds.unwrap_or(0) as i64;
+        if total == 0 {
+            return self.with_vat_applied(session.session_energy.0 * self.price_at(started));
+        }
+
+        let end = started.0 + chrono::Duration::seconds(total);
+        let mut at = started;
+        let mut cost = 0.0;
+        while at.0 < end {
+            let slice_end = (at.0 + self.until_next_edge(at)).min(end);
+            let share = (slice_end - at.0).num_seconds() as f64 / total as f64;
+            cost += session.session_energy.0 * share * self.price_at(at);
+            at = UtcDateTime(slice_end);
+        }
+        self.with_vat_applied(cost)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveTime;
+
+    use super::{FeeSchedule, Tariff};
+    use crate::api::{ChargingSession, UtcDateTime};
+    use crate::units::KilowattHours;
+
+    #[test]
+    fn tiered_grid_tariff() {
+        let fees = FeeSchedule::default()
+            .with_grid_tier(100.0, 0.20)
+            .with_grid_tier(0.0, 0.10)
+            .with_monthly_fee(5.0);
+        assert_eq!(fees.grid_cost(50.0), 5.0);
+        assert!((fees.grid_cost(150.0) - 20.0).abs() < 1e-9);
+
+        let breakdown = fees.breakdown(&[], 2);
+        assert_eq!(breakdown.total(), 10.0);
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn exact_total() {
+        let breakdown = super::CostBreakdown {
+            energy: 0.1,
+            grid: 0.2,
+            session_fees: 0.0,
+            fixed_fees: 0.0,
+        };
+        assert_ne!(breakdown.total(), 0.3);
+        assert_eq!(
+            breakdown.exact_total(),
+            Some(rust_decimal::Decimal::new(3, 1))
+        );
+    }
+
+    #[test]
+    fn time_of_use_tariff() {
+        let hm = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let at = |s: &str| UtcDateTime(s.parse().unwrap());
+        let tariff = Tariff::fixed(0.30)
+            .with_band(hm(22, 0), hm(6, 0), 0.10)
+            .with_vat(25.0);
+        assert_eq!(tariff.price_at(at("2024-05-01T23:30:00Z")), 0.10);
+        assert_eq!(tariff.price_at(at("2024-05-01T06:00:00Z")), 0.30);
+
+        let session = ChargingSession {
+            charger_id: None,
+            session_energy: KilowattHours(10.0),
+            session_start: None,
+            session_stop: None,
+            session_id: None,
+            charge_duration_in_seconds: Some(4 * 3600),
+            first_energy_transfer_period_start: None,
+            last_energy_transfer_period_end: None,
+            price_per_kwh_including_vat: None,
+            price_per_kwh_excluding_vat: None,
+            vat_percentage: None,
+            currency_id: None,
+            cost_including_vat: None,
+            cost_excluding_vat: None,
+        };
+        // Half of the energy at each price
+        let cost = tariff.session_cost(&session, at("2024-05-01T20:00:00Z"));
+        assert!((cost - (5.0 * 0.30 + 5.0 * 0.10) * 1.25).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// A field that changed between two snapshots, named as in the API
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+/// Fields that differ between two snapshots of a charger state, as returned
+/// by [`crate::api::ChargerState::diff`], in alphabetical order
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct StateDiff {
+    pub changes: Vec<FieldChange>,
+}
+
+impl StateDiff {
+    /// Compare the JSON representations of two snapshots, field by field
+    pub(crate) fn between<T: Serialize>(old: &T, new: &T) -> Self {
+        let fields = |v: &T| match serde_json::to_value(v) {
+            Ok(Value::Object(map)) => map,
+            _ => Default::default(),
+        };
+        let (old, mut new) = (fields(old), fields(new));
+
+        let mut changes: Vec<FieldChange> = old
+            .into_iter()
+            .filter_map(|(field, old)| {
+                let new = new.remove(&field).unwrap_or(Value::Null);
+                (old != new).then_some(FieldChange { field, old, new })
+            })
+            .collect();
+        changes.extend(new.into_iter().map(|(field, new)| FieldChange {
+            field,
+            old: Value::Null,
+            new,
+        }));
+        changes.sort_by(|a, b| a.field.cmp(&b.field));
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FieldChange> {
+        self.changes.iter()
+    }
+
+    /// Change of the field with the given API name, such as `chargerOpMode`
+    pub fn get(&self, field: &str) -> Option<&FieldChange> {
+        self.changes.iter().find(|c| c.field == field)
+    }
+}
+
+/// One change per line, such as `totalPower: 7.2 -> 0.0`
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {} -> {}", change.field, change.old, change.new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::StateDiff;
+
+    #[test]
+    fn changed_fields() {
+        let old = json!({"chargerOpMode": 3, "totalPower": 7.2, "isOnline": true});
+        let new =
+            json!({"chargerOpMode": 4, "totalPower": 0.0, "isOnline": true, "voltage": 231.0});
+        let diff = StateDiff::between(&old, &new);
+
+        assert_eq!(diff.len(), 3);
+        assert_eq!(diff.get("chargerOpMode").unwrap().new, json!(4));
+        assert!(diff.get("isOnline").is_none());
+        assert_eq!(diff.get("voltage").unwrap().old, json!(null));
+        assert_eq!(
+            diff.to_string(),
+            "chargerOpMode: 3 -> 4\ntotalPower: 7.2 -> 0.0\nvoltage: null -> 231.0"
+        );
+        assert!(StateDiff::between(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+struct Entry {
+    etag: String,
+    body: Value,
+    used: u64,
+}
+
+/// Responses of GET requests along with their `ETag`, so that they can be
+/// revalidated with `If-None-Match` instead of being downloaded again. The
+/// least recently used responses are evicted beyond `capacity`.
+pub(crate) struct EtagCache {
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    clock: u64,
+}
+
+impl EtagCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn etag(&self, path: &str) -> Option<&str> {
+        self.entries.get(path).map(|e| e.etag.as_str())
+    }
+
+    /// Body of a response found unchanged by the server
+    pub(crate) fn revalidated(&mut self, path: &str) -> Option<Value> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(path)?;
+        entry.used = self.clock;
+        Some(entry.body.clone())
+    }
+
+    pub(crate) fn insert(&mut self, path: &str, etag: &str, body: &Value) {
+        self.clock += 1;
+        if !self.entries.contains_key(path) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            path.to_owned(),
+            Entry {
+                etag: etag.to_owned(),
+                body: body.clone(),
+                used: self.clock,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::EtagCache;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = EtagCache::new(2);
+        cache.insert("sites", "\"a\"", &json!([1]));
+        cache.insert("chargers", "\"b\"", &json!([2]));
+        assert_eq!(cache.revalidated("sites"), Some(json!([1])));
+
+        cache.insert("products", "\"c\"", &json!([3]));
+        assert_eq!(cache.etag("sites"), Some("\"a\""));
+        assert_eq!(cache.etag("chargers"), None);
+        assert_eq!(cache.etag("products"), Some("\"c\""));
+    }
+}
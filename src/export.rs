@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
+
+use crate::{
+    api::UtcDateTime,
+    ids::ChargerId,
+    observation::{Event, Observation},
+};
+
+/// A numeric data point, ready to be written to a time series database.
+/// When points were suppressed by an [`OutputThrottle`], `value` is the
+/// latest one and `min`/`max` span all of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub charger: ChargerId,
+    pub code: u16,
+    pub time: UtcDateTime,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+
+    /// Number of values aggregated into this point
+    pub count: u32,
+}
+
+/// Numeric value of an observation, if it has one
+pub fn numeric_value(obs: &Observation) -> Option<f64> {
+    use Observation::*;
+    Some(match obs {
+        TotalPower(v) | EnergyPerHour(v) | LifetimeEnergy(v) | DynamicChargerCurrent(v) => *v,
+        CableRating(v) => *v,
+        CircuitMaxCurrent { amperes, .. } | CircuitTotalCurrent { amperes, .. } => *amperes,
+        IntCurrent { current, .. } => *current,
+        IntVoltage { voltage, .. } => *voltage,
+        Temperature(v) | MaximumTemperature(v) | LifetimeRelaySwitches(v) | LifetimeHours(v) => {
+            *v as f64
+        }
+        IsEnabled(b) | SmartCharging(b) | CableLocked(b) | TriplePhase(b) => *b as u8 as f64,
+        ChargerOpMode(mode) => u8::from(*mode) as f64,
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Debug)]
+struct Window {
+    last_emit: UtcDateTime,
+    pending: Option<Point>,
+}
+
+/// Limits the rate of points written per charger and per observation code,
+/// such as at most one power point every 10 seconds. Suppressed values are
+/// aggregated into the next emitted point.
+#[derive(Clone, Debug, Default)]
+pub struct OutputThrottle {
+    default_interval: Option<Duration>,
+    intervals: HashMap<u16, Duration>,
+    windows: HashMap<(ChargerId, u16), Window>,
+}
+
+impl OutputThrottle {
+    /// No limit until configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Minimum interval between two points, for codes without a specific one
+    pub fn default_interval(mut self, interval: Duration) -> Self {
+        self.default_interval = Some(interval);
+        self
+    }
+
+    /// Minimum interval between two points of the given observation code
+    pub fn interval(mut self, code: u16, interval: Duration) -> Self {
+        self.intervals.insert(code, interval);
+        self
+    }
+
+    /// Feed a stream event, returning the point to write, if any
+    pub fn push_event(&mut self, event: &Event, now: UtcDateTime) -> Option<Point> {
+        let code = event.observation.code()?;
+        let value = numeric_value(&event.observation)?;
+        self.push(&event.charger, code, value, now)
+    }
+
+    /// Feed a value, returning the point to write, if any
+    pub fn push(
+        &mut self,
+        charger: &ChargerId,
+        code: u16,
+        value: f64,
+        now: UtcDateTime,
+    ) -> Option<Point> {
+        let point = Point {
+            charger: charger.clone(),
+            code,
+            time: now,
+            value,
+            min: value,
+            max: value,
+            count: 1,
+        };
+        let Some(interval) = self.intervals.get(&code).or(self.default_interval.as_ref()) else {
+            return Some(point);
+        };
+
+        let key = (charger.clone(), code);
+        let Some(window) = self.windows.get_mut(&key) else {
+            self.windows.insert(
+                key,
+                Window {
+                    last_emit: now,
+                    pending: None,
+                },
+            );
+            return Some(point);
+        };
+
+        let merged = match window.pending.take() {
+            Some(pending) => Point {
+                min: pending.min.min(value),
+                max: pending.max.max(value),
+                count: pending.count + 1,
+                ..point
+            },
+            None => point,
+        };
+
+        if now.0 - window.last_emit.0 >= *interval {
+            window.last_emit = now;
+            Some(merged)
+        } else {
+            window.pending = Some(merged);
+            None
+        }
+    }
+
+    /// Emit the aggregated points whose interval is over, so that the latest
+    /// values are written even when no new value comes in
+    pub fn flush(&mut self, now: UtcDateTime) -> Vec<Point> {
+        let (intervals, default) = (&self.intervals, self.default_interval);
+        self.windows
+            .iter_mut()
+            .filter_map(|((_, code), window)| {
+                let interval = intervals.get(code).copied().or(default)?;
+                if now.0 - window.last_emit.0 < interval {
+                    return None;
+                }
+                let point = window.pending.take()?;
+                window.last_emit = now;
+                Some(point)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::OutputThrottle;
+    use crate::{api::UtcDateTime, ids::ChargerId, observation::ids};
+
+    #[test]
+    fn aggregate_suppressed_points() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let at = |secs| UtcDateTime(t0 + Duration::seconds(secs));
+        let power = ids::TOTAL_POWER;
+        let mut throttle = OutputThrottle::new().interval(power, Duration::seconds(10));
+        let eh1: ChargerId = "EH1".parse().unwrap();
+        let eh2: ChargerId = "EH2".parse().unwrap();
+
+        assert!(throttle.push(&eh1, power, 1.0, at(0)).is_some());
+        assert!(throttle.push(&eh1, power, 5.0, at(3)).is_none());
+        assert!(throttle.push(&eh1, power, 2.0, at(6)).is_none());
+        assert!(throttle.push(&eh2, power, 7.0, at(6)).is_some());
+        assert!(throttle.push(&eh1, 109, 3.0, at(7)).is_some());
+
+        let point = throttle.push(&eh1, power, 3.0, at(11)).unwrap();
+        assert_eq!(point.charger, eh1);
+        assert_eq!(
+            (point.value, point.min, point.max, point.count),
+            (3.0, 2.0, 5.0, 3)
+        );
+
+        assert!(throttle.push(&eh1, power, 4.0, at(12)).is_none());
+        assert!(throttle.flush(at(15)).is_empty());
+        let flushed = throttle.flush(at(21));
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].value, 4.0);
+    }
+}
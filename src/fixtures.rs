@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::{api::CapturedBodies, schema::endpoint_template, secret::redact};
+
+/// Replaces identifiers and personal data in API payloads with placeholders,
+/// so that they can be shared as test fixtures. A given identifier is always
+/// replaced by the same placeholder, keeping the payloads consistent with
+/// each other.
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    strings: HashMap<String, String>,
+    numbers: HashMap<i64, i64>,
+}
+
+/// Keys holding identifiers, lowercased. Listed explicitly, as keys like
+/// `currencyId` hold public codes.
+const ID_KEYS: &[&str] = &[
+    "id",
+    "mid",
+    "uuid",
+    "chargerid",
+    "siteid",
+    "circuitid",
+    "equalizerid",
+    "userid",
+    "sessionid",
+    "serialnumber",
+    "sitekey",
+];
+
+fn is_id_key(key: &str) -> bool {
+    ID_KEYS.contains(&key)
+}
+
+/// Observations of the stream, whose `id` is the observation code
+fn is_observation(map: &Map<String, Value>) -> bool {
+    map.keys().any(|k| k.eq_ignore_ascii_case("datatype"))
+}
+
+fn is_personal_key(key: &str) -> bool {
+    [
+        "name", "email", "phone", "street", "address", "city", "zip", "iccid",
+    ]
+    .iter()
+    .any(|k| key.contains(k))
+}
+
+/// Charger and equalizer serial numbers, such as `EH123456`
+fn looks_like_serial(s: &str) -> bool {
+    let letters = s.chars().take_while(|c| c.is_ascii_uppercase()).count();
+    (1..=3).contains(&letters)
+        && s.len() >= 6
+        && s[letters..].chars().all(|c| c.is_ascii_alphanumeric())
+        && s[letters..].chars().any(|c| c.is_ascii_digit())
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Placeholder for an identifier, keeping its alphabetic prefix and length
+    fn id(&mut self, s: &str) -> String {
+        let next = self.strings.len() + 1;
+        self.strings
+            .entry(s.to_owned())
+            .or_insert_with(|| {
+                let prefix: String = s.chars().take_while(|c| c.is_ascii_uppercase()).collect();
+                let width = s.len().saturating_sub(prefix.len()).max(1);
+                format!("{prefix}{next:0width$}")
+            })
+            .clone()
+    }
+
+    fn number(&mut self, n: i64) -> i64 {
+        let next = self.numbers.len() as i64 + 1;
+        *self.numbers.entry(n).or_insert(next)
+    }
+
+    fn personal(&mut self, key: &str, s: &str) -> String {
+        let next = self.strings.len() + 1;
+        self.strings
+            .entry(s.to_owned())
+            .or_insert_with(|| {
+                if key.contains("email") {
+                    format!("user{next}@example.com")
+                } else {
+                    format!("{key}-{next}")
+                }
+            })
+            .clone()
+    }
+
+    fn scrub(&mut self, key: Option<&str>, value: Value) -> Value {
+        match (key, value) {
+            (_, Value::Object(map)) => {
+                let observation = is_observation(&map);
+                Value::Object(
+                    map.into_iter()
+                        .map(|(k, v)| {
+                            let key = k.to_lowercase();
+                            let v = match v {
+                                Value::Number(_) if observation && key == "id" => v,
+                                v => self.scrub(Some(&key), v),
+                            };
+                            (k, v)
+                        })
+                        .collect::<Map<_, _>>(),
+                )
+            }
+            (_, Value::Array(items)) => {
+                Value::Array(items.into_iter().map(|v| self.scrub(key, v)).collect())
+            }
+            (Some(k), Value::String(s)) if is_id_key(k) => Value::String(self.id(&s)),
+            (Some(k), Value::Number(n)) if is_id_key(k) => match n.as_i64() {
+                Some(n) => json!(self.number(n)),
+                None => Value::Number(n),
+            },
+            (Some(k), Value::String(s)) if is_personal_key(k) => {
+                Value::String(self.personal(k, &s))
+            }
+            (_, Value::String(s)) if looks_like_serial(&s) => Value::String(self.id(&s)),
+            (_, other) => other,
+        }
+    }
+
+    /// Scrub a payload. Secrets are redacted, identifiers and personal data
+    /// replaced.
+    pub fn anonymize(&mut self, value: Value) -> Value {
+        self.scrub(None, redact(value))
+    }
+
+    /// Scrub the identifiers found in the segments of an API path
+    pub fn anonymize_path(&mut self, path: &str) -> String {
+        path.split('/')
+            .map(|seg| match seg.parse::<i64>() {
+                Ok(n) => self.number(n).to_string(),
+                Err(_) if looks_like_serial(seg) => self.id(seg),
+                Err(_) => seg.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+struct Recorder {
+    dir: PathBuf,
+    anonymizer: Anonymizer,
+    count: u32,
+}
+
+/// Writes anonymized payloads into a directory, one JSON file per payload,
+/// to be contributed as regression test inputs. Review the files before
+/// sharing them: free-form fields may still contain personal data.
+#[derive(Clone)]
+pub struct FixtureRecorder {
+    inner: Arc<Mutex<Recorder>>,
+}
+
+impl FixtureRecorder {
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Recorder {
+                dir: dir.as_ref().to_owned(),
+                anonymizer: Anonymizer::new(),
+                count: 0,
+            })),
+        })
+    }
+
+    /// Record a payload, under a name describing its kind
+    pub fn record(&self, kind: &str, value: Value) -> io::Result<PathBuf> {
+        let mut recorder = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let value = recorder.anonymizer.anonymize(value);
+        recorder.count += 1;
+        let kind: String = kind
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = recorder
+            .dir
+            .join(format!("{:04}-{kind}.json", recorder.count));
+        fs::write(&path, serde_json::to_vec_pretty(&value)?)?;
+        Ok(path)
+    }
+
+    /// Record an API call captured by [`Context::capture_bodies`](crate::api::Context::capture_bodies)
+    pub fn record_bodies(&self, bodies: &CapturedBodies) -> io::Result<PathBuf> {
+        let path = self
+            .inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .anonymizer
+            .anonymize_path(bodies.path);
+        let kind = format!("{}-{}", bodies.method, endpoint_template(bodies.path));
+        self.record(
+            &kind,
+            json!({
+                "method": bodies.method,
+                "path": path,
+                "status": bodies.status,
+                "request": bodies.request,
+                "response": bodies.response,
+            }),
+        )
+    }
+
+    /// Callback for [`Context::capture_bodies`](crate::api::Context::capture_bodies)
+    pub fn body_callback(&self) -> impl FnMut(&CapturedBodies) + Send + 'static {
+        let recorder = self.clone();
+        move |bodies| {
+            if let Err(e) = recorder.record_bodies(bodies) {
+                warn!("Could not record fixture: {e}");
+            }
+        }
+    }
+
+    /// Handler for [`Stream::on_invocation`](crate::observation::Stream::on_invocation),
+    /// recording stream frames
+    #[cfg(feature = "tungstenite")]
+    pub fn invocation_handler(&self) -> impl FnMut(&str, &[Value]) + Send + 'static {
+        let recorder = self.clone();
+        move |target, arguments| {
+            let kind = format!("stream-{target}");
+            if let Err(e) = recorder.record(&kind, Value::from(arguments.to_vec())) {
+                warn!("Could not record fixture: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::Anonymizer;
+
+    #[test]
+    fn consistent_placeholders() {
+        let mut a = Anonymizer::new();
+        let site = a.anonymize(json!({
+            "id": 4242,
+            "siteKey": "AB12-CD34",
+            "name": "Home",
+            "contactInfo": {"installerEmail": "bob@example.org"},
+            "circuits": [{"id": 17, "chargers": [{"id": "EH123456", "name": "Garage"}]}],
+            "accessToken": "xyz",
+            "ratedCurrent": 32,
+        }));
+        let charger =
+            a.anonymize(json!({"dataType": 3, "id": 120, "mid": "EH123456", "value": "1.5"}));
+        let session = a.anonymize(json!({"chargerId": "EH123456", "currencyId": "EUR"}));
+
+        assert_eq!(site["circuits"][0]["chargers"][0]["id"], charger["mid"]);
+        assert_ne!(charger["mid"], "EH123456");
+        assert!(charger["mid"].as_str().unwrap().starts_with("EH"));
+        assert_ne!(site["id"], 4242);
+        assert_ne!(site["name"], "Home");
+        assert_eq!(site["contactInfo"]["installerEmail"], "user3@example.com");
+        assert_eq!(site["accessToken"], "<secret>");
+        assert_eq!(site["ratedCurrent"], 32);
+        assert_eq!(charger["value"], "1.5");
+        assert_eq!(charger["id"], 120);
+        assert_eq!(session["chargerId"], charger["mid"]);
+        assert_eq!(session["currencyId"], "EUR");
+        assert_eq!(
+            a.anonymize_path("chargers/EH123456/sessions"),
+            format!("chargers/{}/sessions", charger["mid"].as_str().unwrap())
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use tracing::warn;
+
+use crate::api::{ApiError, Charger, ChargerState, ChargingSession, CommandReply, Context};
+use crate::ids::ChargerId;
+
+/// A command that can be sent to any charger
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    Start,
+    Pause,
+    Resume,
+    Stop,
+    Deauthorize,
+}
+
+impl Command {
+    pub(crate) fn path(self) -> &'static str {
+        match self {
+            Command::Start => "start_charging",
+            Command::Pause => "pause_charging",
+            Command::Resume => "resume_charging",
+            Command::Stop => "stop_charging",
+            Command::Deauthorize => "deauthorize",
+        }
+    }
+}
+
+/// Result of a command for one charger
+#[derive(Debug)]
+pub struct CommandOutcome {
+    pub charger: ChargerId,
+    pub result: Result<Option<CommandReply>, ApiError>,
+}
+
+impl CommandOutcome {
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Send a command to each of the chargers, one after the other. A failure
+/// for one charger does not prevent the command from being sent to the others.
+/// Outcomes are returned in the order of `chargers`.
+pub fn run_command(
+    ctx: &mut Context,
+    chargers: &[ChargerId],
+    command: Command,
+) -> Vec<CommandOutcome> {
+    chargers.iter().map(|id| send(ctx, id, command)).collect()
+}
+
+/// Like [`run_command`], with up to `max_in_flight` commands sent at once
+/// from separate threads.
+///
+/// The threads use copies of the access token of `ctx`, refreshed beforehand
+/// if needed. They cannot refresh it themselves, so a token revoked during the
+/// run fails the remaining commands.
+pub fn run_command_concurrent(
+    ctx: &mut Context,
+    chargers: &[ChargerId],
+    command: Command,
+    max_in_flight: usize,
+) -> Result<Vec<CommandOutcome>, ApiError> {
+    concurrently(ctx, chargers, max_in_flight, |ctx, id| {
+        send(ctx, id, command)
+    })
+}
+
+/// States of chargers, by charger ID
+pub type ChargerStates = Vec<(ChargerId, Result<ChargerState, ApiError>)>;
+
+/// Read the state of all the chargers, up to `parallelism` at once. Results
+/// are returned in the order of `chargers`, along with the charger IDs. The
+/// same limitations as for [`run_command_concurrent`] apply.
+pub fn states(
+    ctx: &mut Context,
+    chargers: &[Charger],
+    parallelism: usize,
+) -> Result<ChargerStates, ApiError> {
+    concurrently(ctx, chargers, parallelism, |ctx, charger| {
+        (charger.id.clone(), charger.state(ctx))
+    })
+}
+
+/// Ongoing charging sessions of chargers, by charger ID
+pub type OngoingSessions = Vec<(ChargerId, Result<Option<ChargingSession>, ApiError>)>;
+
+/// Read the ongoing session of all the chargers, up to `parallelism` at once.
+/// Results are returned in the order of `chargers`, along with the charger
+/// IDs. The same limitations as for [`run_command_concurrent`] apply.
+pub fn ongoing_sessions(
+    ctx: &mut Context,
+    chargers: &[Charger],
+    parallelism: usize,
+) -> Result<OngoingSessions, ApiError> {
+    concurrently(ctx, chargers, parallelism, |ctx, charger| {
+        (charger.id.clone(), charger.ongoing_session(ctx))
+    })
+}
+
+/// Apply `f` to all the items, from up to `workers` threads with their own
+/// copy of the context. Results are in the order of `items`.
+fn concurrently<I, R, F>(
+    ctx: &mut Context,
+    items: &[I],
+    workers: usize,
+    f: F,
+) -> Result<Vec<R>, ApiError>
+where
+    I: Sync,
+    R: Send,
+    F: Fn(&mut Context, &I) -> R + Sync,
+{
+    let workers = workers.min(items.len());
+    if workers <= 1 {
+        return Ok(items.iter().map(|item| f(ctx, item)).collect());
+    }
+
+    let contexts = (0..workers)
+        .map(|_| ctx.fork())
+        .collect::<Result<Vec<_>, _>>()?;
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+
+    thread::scope(|scope| {
+        for mut ctx in contexts {
+            let (next, results, f) = (&next, &results, &f);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(&mut ctx, item);
+                results
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, r)| r).collect())
+}
+
+fn send(ctx: &mut Context, charger: &ChargerId, command: Command) -> CommandOutcome {
+    let result = ctx.charger_command(charger, command.path());
+    if let Err(e) = &result {
+        warn!("Charger {charger}: {command:?} failed: {e}");
+    }
+    CommandOutcome {
+        charger: charger.clone(),
+        result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{concurrently, run_command_concurrent, Command};
+    use crate::api::Context;
+    use crate::config::ClientConfig;
+    use crate::ids::ChargerId;
+
+    fn context() -> Context {
+        Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(3600),
+        )
+    }
+
+    #[test]
+    fn concurrent_order_and_errors() {
+        let items: Vec<u64> = (0..20).collect();
+        let results = concurrently(&mut context(), &items, 4, |_, &i| {
+            // Later items finish first
+            thread::sleep(Duration::from_millis(20 - i));
+            if i % 5 == 0 {
+                Err(i)
+            } else {
+                Ok(i * 10)
+            }
+        })
+        .unwrap();
+
+        let expected: Vec<_> = items
+            .iter()
+            .map(|&i| if i % 5 == 0 { Err(i) } else { Ok(i * 10) })
+            .collect();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn single_worker_runs_inline() {
+        let caller = thread::current().id();
+        for workers in [0, 1, 8] {
+            let results = concurrently(&mut context(), &[7], workers, |_, &i| {
+                (i, thread::current().id() == caller)
+            })
+            .unwrap();
+            assert_eq!(results, [(7, true)]);
+        }
+        let none: [u32; 0] = [];
+        assert!(concurrently(&mut context(), &none, 4, |_, &i| i)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn failed_commands_are_isolated() {
+        // Nothing listens on the discard port, every command fails at once
+        let mut ctx = context().config(ClientConfig::default().api_base("http://127.0.0.1:9/"));
+        let chargers: Vec<ChargerId> = ["EH000001", "EH000002", "EH000003"]
+            .iter()
+            .map(|id| id.parse().unwrap())
+            .collect();
+        let outcomes = run_command_concurrent(&mut ctx, &chargers, Command::Pause, 2).unwrap();
+
+        let ids: Vec<_> = outcomes.iter().map(|o| o.charger.clone()).collect();
+        assert_eq!(ids, chargers);
+        assert!(outcomes.iter().all(|o| !o.is_success()));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{borrow::Borrow, fmt, ops::Deref, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiError;
+
+/// Serial number of a charger, such as `EH123456`, as used in the API paths.
+/// Equalizers are identified by the same kind of serial number, such as
+/// `QH123456`. Only ASCII letters and digits are allowed.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChargerId(String);
+
+impl ChargerId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ChargerId {
+    type Error = ApiError;
+
+    fn try_from(id: String) -> Result<Self, ApiError> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ApiError::InvalidID(id));
+        }
+        Ok(ChargerId(id))
+    }
+}
+
+impl FromStr for ChargerId {
+    type Err = ApiError;
+
+    fn from_str(id: &str) -> Result<Self, ApiError> {
+        id.to_owned().try_into()
+    }
+}
+
+impl From<ChargerId> for String {
+    fn from(id: ChargerId) -> Self {
+        id.0
+    }
+}
+
+impl Deref for ChargerId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ChargerId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for ChargerId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for ChargerId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for ChargerId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for ChargerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// ID of a site
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct SiteId(pub u32);
+
+impl FromStr for SiteId {
+    type Err = ApiError;
+
+    fn from_str(id: &str) -> Result<Self, ApiError> {
+        id.parse()
+            .map(SiteId)
+            .map_err(|_| ApiError::InvalidID(id.to_owned()))
+    }
+}
+
+impl From<u32> for SiteId {
+    fn from(id: u32) -> Self {
+        SiteId(id)
+    }
+}
+
+impl fmt::Display for SiteId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// ID of a circuit, unique across sites
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct CircuitId(pub u32);
+
+impl FromStr for CircuitId {
+    type Err = ApiError;
+
+    fn from_str(id: &str) -> Result<Self, ApiError> {
+        id.parse()
+            .map(CircuitId)
+            .map_err(|_| ApiError::InvalidID(id.to_owned()))
+    }
+}
+
+impl From<u32> for CircuitId {
+    fn from(id: u32) -> Self {
+        CircuitId(id)
+    }
+}
+
+impl fmt::Display for CircuitId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChargerId, CircuitId, SiteId};
+
+    #[test]
+    fn parse_ids() {
+        let id: ChargerId = "EH123456".parse().unwrap();
+        assert_eq!(id, "EH123456");
+        assert!("EH12/3456".parse::<ChargerId>().is_err());
+        assert!("".parse::<ChargerId>().is_err());
+        assert!(serde_json::from_str::<ChargerId>(r#""../sites""#).is_err());
+
+        assert_eq!("42".parse::<SiteId>().unwrap(), SiteId(42));
+        assert!("site".parse::<CircuitId>().is_err());
+        assert_eq!(serde_json::to_string(&CircuitId(7)).unwrap(), "7");
+    }
+}
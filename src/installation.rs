@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use crate::api::{ApiError, Charger, Circuit, Context, Equalizer, SiteDetails};
+use crate::ids::{ChargerId, CircuitId, SiteId};
+
+/// A site, with its circuits and chargers, and its equalizers
+#[derive(Clone, Debug)]
+pub struct InstalledSite {
+    pub details: SiteDetails,
+    pub equalizers: Vec<Equalizer>,
+}
+
+#[derive(Clone, Debug)]
+struct Located {
+    charger: Charger,
+    site: Option<usize>,
+    circuit: Option<usize>,
+}
+
+/// The whole topology available to the user: sites, circuits, chargers and
+/// equalizers, with lookups by ID and name.
+#[derive(Clone, Debug)]
+pub struct Installation {
+    sites: Vec<InstalledSite>,
+    chargers: HashMap<ChargerId, Located>,
+    charger_names: HashMap<String, Vec<ChargerId>>,
+}
+
+impl Installation {
+    /// Fetch the complete topology. This costs two API calls per site, plus two.
+    pub fn load(ctx: &mut Context) -> Result<Self, ApiError> {
+        let mut sites = vec![];
+        for site in ctx.sites()? {
+            sites.push(InstalledSite {
+                details: site.details(ctx)?,
+                equalizers: site.equalizers(ctx)?,
+            });
+        }
+        Ok(Self::new(sites, ctx.chargers()?))
+    }
+
+    /// Build the lookup tables. `chargers` may list chargers which are not part of any site.
+    pub fn new(sites: Vec<InstalledSite>, chargers: Vec<Charger>) -> Self {
+        let mut located: HashMap<ChargerId, Located> = HashMap::new();
+        for (s, site) in sites.iter().enumerate() {
+            for (c, circuit) in site.details.circuits.iter().enumerate() {
+                for charger in &circuit.chargers {
+                    located.insert(
+                        charger.id.clone(),
+                        Located {
+                            charger: charger.clone(),
+                            site: Some(s),
+                            circuit: Some(c),
+                        },
+                    );
+                }
+            }
+        }
+        for charger in chargers {
+            located.entry(charger.id.clone()).or_insert(Located {
+                charger,
+                site: None,
+                circuit: None,
+            });
+        }
+
+        let mut charger_names: HashMap<String, Vec<ChargerId>> = HashMap::new();
+        for l in located.values() {
+            charger_names
+                .entry(l.charger.name.clone())
+                .or_default()
+                .push(l.charger.id.clone());
+        }
+        for ids in charger_names.values_mut() {
+            ids.sort();
+        }
+
+        Self {
+            sites,
+            chargers: located,
+            charger_names,
+        }
+    }
+
+    pub fn sites(&self) -> &[InstalledSite] {
+        &self.sites
+    }
+
+    pub fn site(&self, id: SiteId) -> Option<&InstalledSite> {
+        self.sites.iter().find(|s| s.details.site.id == id)
+    }
+
+    pub fn site_by_name(&self, name: &str) -> Option<&InstalledSite> {
+        self.sites
+            .iter()
+            .find(|s| s.details.site.name.as_deref() == Some(name))
+    }
+
+    /// All chargers, including those not attached to a site
+    pub fn chargers(&self) -> impl Iterator<Item = &Charger> {
+        self.chargers.values().map(|l| &l.charger)
+    }
+
+    pub fn charger(&self, id: &ChargerId) -> Option<&Charger> {
+        self.chargers.get(id).map(|l| &l.charger)
+    }
+
+    /// The charger with this exact name, if there is a single one
+    pub fn charger_by_name(&self, name: &str) -> Option<&Charger> {
+        match self.charger_names.get(name).map(Vec::as_slice) {
+            Some([id]) => self.charger(id),
+            _ => None,
+        }
+    }
+
+    /// All the chargers with this exact name, by ID
+    pub fn chargers_named(&self, name: &str) -> Vec<&Charger> {
+        self.charger_names
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.charger(id))
+            .collect()
+    }
+
+    /// Find the chargers whose name resembles `query`, best matches first.
+    /// Case, spaces and punctuation are ignored, and small typos are tolerated.
+    pub fn search_chargers(&self, query: &str) -> Vec<&Charger> {
+        let mut found: Vec<(usize, &Charger)> = self
+            .chargers()
+            .filter_map(|c| Some((match_score(&c.name, query)?, c)))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        found.into_iter().map(|(_, c)| c).collect()
+    }
+
+    /// Find the charger best matching `name`, if there is no ambiguity
+    pub fn charger_by_fuzzy_name(&self, name: &str) -> Option<&Charger> {
+        if let Some(c) = self.charger_by_name(name) {
+            return Some(c);
+        }
+        let mut scores: Vec<(usize, &Charger)> = self
+            .chargers()
+            .filter_map(|c| Some((match_score(&c.name, name)?, c)))
+            .collect();
+        scores.sort_by_key(|(score, _)| *score);
+        match &*scores {
+            [(_, c)] => Some(c),
+            [(best, c), (next, _), ..] if best < next => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Site the charger is attached to
+    pub fn site_of(&self, charger_id: &ChargerId) -> Option<&InstalledSite> {
+        let site = self.chargers.get(charger_id)?.site?;
+        Some(&self.sites[site])
+    }
+
+    /// Circuit the charger is attached to
+    pub fn circuit_of(&self, charger_id: &ChargerId) -> Option<&Circuit> {
+        let located = self.chargers.get(charger_id)?;
+        Some(&self.sites[located.site?].details.circuits[located.circuit?])
+    }
+
+    pub fn circuit(&self, id: CircuitId) -> Option<&Circuit> {
+        self.sites
+            .iter()
+            .flat_map(|s| &s.details.circuits)
+            .find(|c| c.id == id)
+    }
+
+    pub fn equalizers(&self) -> impl Iterator<Item = &Equalizer> {
+        self.sites.iter().flat_map(|s| &s.equalizers)
+    }
+
+    pub fn equalizer(&self, id: &ChargerId) -> Option<&Equalizer> {
+        self.equalizers().find(|e| &e.id == id)
+    }
+}
+
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(current)
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+/// How well a name matches a query, lower is better
+fn match_score(name: &str, query: &str) -> Option<usize> {
+    let (name, query) = (normalize(name), normalize(query));
+    if query.is_empty() {
+        return None;
+    }
+    if name == query {
+        return Some(0);
+    }
+    if name.starts_with(&query) {
+        return Some(1);
+    }
+    if name.contains(&query) {
+        return Some(2);
+    }
+    let distance = levenshtein(&name, &query);
+    (distance <= (query.len() / 4).max(1)).then_some(3 + distance)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{match_score, Installation, InstalledSite};
+    use crate::api::Charger;
+
+    fn charger(id: &str, name: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "name": name,
+            "productCode": 1,
+            "createdOn": "2024-01-01T00:00:00",
+            "updatedOn": "2024-01-01T00:00:00",
+            "levelOfAccess": 1,
+        })
+    }
+
+    fn installation() -> Installation {
+        let circuit = json!({
+            "id": 7,
+            "uuid": "",
+            "siteId": 42,
+            "circuitPanelId": 1,
+            "panelName": "1",
+            "ratedCurrent": 32.0,
+            "fuse": 32.0,
+            "chargers": [charger("EH000001", "Garage"), charger("EH000002", "Carport")],
+            "useDynamicMaster": false,
+        });
+        let site = InstalledSite {
+            details: serde_json::from_value(json!({
+                "id": 42,
+                "name": "Home",
+                "levelOfAccess": 1,
+                "circuits": [circuit],
+            }))
+            .unwrap(),
+            equalizers: vec![],
+        };
+        let chargers: Vec<Charger> = serde_json::from_value(json!([
+            charger("EH000003", "Garage"),
+            charger("EH000004", "Cabin")
+        ]))
+        .unwrap();
+        Installation::new(vec![site], chargers)
+    }
+
+    #[test]
+    fn lookups() {
+        let installation = installation();
+        let id = |c: Option<&Charger>| c.map(|c| c.id.to_string());
+
+        assert_eq!(installation.chargers().count(), 4);
+        assert_eq!(
+            id(installation.charger_by_name("Carport")),
+            Some("EH000002".into())
+        );
+        assert_eq!(id(installation.charger_by_name("Missing")), None);
+
+        // Both chargers named Garage are kept, and neither is picked
+        let garages: Vec<_> = installation
+            .chargers_named("Garage")
+            .iter()
+            .map(|c| c.id.to_string())
+            .collect();
+        assert_eq!(garages, ["EH000001", "EH000003"]);
+        assert_eq!(id(installation.charger_by_name("Garage")), None);
+        assert_eq!(id(installation.charger_by_fuzzy_name("garage")), None);
+        assert_eq!(
+            id(installation.charger_by_fuzzy_name("cabn")),
+            Some("EH000004".into())
+        );
+
+        let attached = "EH000002".parse().unwrap();
+        let site = installation.site_of(&attached).unwrap();
+        assert_eq!(site.details.site.name.as_deref(), Some("Home"));
+        assert_eq!(installation.circuit_of(&attached).unwrap().id.0, 7);
+        let unattached = "EH000004".parse().unwrap();
+        assert!(installation.site_of(&unattached).is_none());
+        assert!(installation.circuit_of(&unattached).is_none());
+    }
+
+    #[test]
+    fn fuzzy_names() {
+        assert_eq!(match_score("Garage left", "garage-LEFT"), Some(0));
+        assert_eq!(match_score("Garage left", "garage"), Some(1));
+        assert_eq!(match_score("Garage left", "left"), Some(2));
+        assert_eq!(match_score("Garage left", "garage lfet"), Some(5));
+        assert_eq!(match_score("Garage left", "driveway"), None);
+    }
+}
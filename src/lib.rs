@@ -1,10 +1,74 @@
 pub mod api;
 
+#[cfg(feature = "tungstenite")]
+pub mod bootstrap;
+
+#[cfg(feature = "tungstenite")]
+pub use bootstrap::bootstrap;
+
+pub mod cache;
+
+pub mod calibration;
+
+pub mod claims;
+
+pub mod config;
+
+pub mod connectivity;
+
+pub mod cost;
+
+pub mod diff;
+
+pub mod etag;
+
+pub mod export;
+
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+
+pub mod fleet;
+
+pub mod ids;
+
+pub mod installation;
+
+pub mod loadbalance;
+
+pub mod money;
+
+pub mod monitor;
+
+pub mod ratelimit;
+
+pub mod refresher;
+
+pub mod retry;
+
+pub mod schema;
+
+pub mod secret;
+
+pub mod sessions;
+
+pub mod shared;
+
+pub mod shutdown;
+
+pub mod smoothing;
+
+pub mod surplus;
+
+pub mod tokenstore;
+
+pub mod units;
+
+pub mod voltage;
+
 #[cfg(feature = "tungstenite")]
 pub mod stream;
 
 #[cfg(feature = "tungstenite")]
 pub mod signalr;
 
-#[cfg(feature = "tungstenite")]
 pub mod observation;
@@ -0,0 +1,397 @@
+use std::collections::{BTreeMap, HashMap};
+
+use tracing::{debug, info};
+
+use crate::api::{ApiError, Charger, Context, SetCurrent, SiteDetails, Triphase};
+use crate::ids::{ChargerId, CircuitId, SiteId};
+use crate::units::Amperes;
+
+/// How the site budget is split between circuits
+#[derive(Clone, Debug)]
+pub enum Strategy {
+    /// Share proportionally to the rated current of each circuit
+    Proportional,
+
+    /// Serve circuits in the given order, by circuit ID. Circuits not listed are served last.
+    Priority(Vec<CircuitId>),
+}
+
+#[derive(Clone, Debug)]
+struct CircuitBudget {
+    id: CircuitId,
+    rated_current: f64,
+    chargers: Vec<ChargerId>,
+}
+
+/// Current allocated to a circuit, per phase
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Allocation {
+    pub circuit_id: CircuitId,
+    pub current: f64,
+}
+
+/// Keeps the total power drawn by a site under a fixed budget, by adjusting
+/// the dynamic current of its circuits.
+#[derive(Clone, Debug)]
+pub struct BudgetController {
+    site_id: SiteId,
+    budget_kw: f64,
+    strategy: Strategy,
+    circuits: Vec<CircuitBudget>,
+
+    /// Phase-to-neutral voltage, in V
+    pub voltage: f64,
+
+    /// Extra current granted above the measured demand of a circuit, in A
+    pub headroom: f64,
+
+    /// Minimum change of allocation worth sending to the API, in A
+    pub threshold: f64,
+
+    /// Time-to-live of the dynamic currents sent to the API, in minutes
+    pub time_to_live: Option<i32>,
+
+    power: HashMap<ChargerId, f64>,
+    applied: HashMap<CircuitId, f64>,
+}
+
+impl BudgetController {
+    pub fn new(site: &SiteDetails, budget_kw: f64, strategy: Strategy) -> Self {
+        let circuits = site
+            .circuits
+            .iter()
+            .map(|c| CircuitBudget {
+                id: c.id,
+                rated_current: c.rated_current,
+                chargers: c.chargers.iter().map(|ch| ch.id.clone()).collect(),
+            })
+            .collect();
+
+        Self {
+            site_id: site.site.id,
+            budget_kw,
+            strategy,
+            circuits,
+            voltage: 230.0,
+            headroom: 2.0,
+            threshold: 0.5,
+            time_to_live: None,
+            power: HashMap::new(),
+            applied: HashMap::new(),
+        }
+    }
+
+    pub fn set_budget(&mut self, budget_kw: f64) {
+        self.budget_kw = budget_kw;
+    }
+
+    /// Record the power currently drawn by a charger, in kW
+    pub fn observe_power(&mut self, charger: &ChargerId, kw: f64) {
+        self.power.insert(charger.clone(), kw);
+    }
+
+    /// Feed an observation event, streamed or read from the REST API. Returns
+    /// true if the allocation changed enough that it should be applied again.
+    pub fn observe(&mut self, event: &crate::observation::Event) -> bool {
+        let crate::observation::Observation::TotalPower(kw) = event.observation else {
+            return false;
+        };
+        self.observe_power(&event.charger, kw);
+        self.pending().next().is_some()
+    }
+
+    /// Total power currently drawn by the site, in kW
+    pub fn total_power(&self) -> f64 {
+        self.power.values().sum()
+    }
+
+    /// Per-phase current the circuit is likely to use, or its rated current if unknown
+    fn cap(&self, circuit: &CircuitBudget) -> f64 {
+        let known: Vec<f64> = circuit
+            .chargers
+            .iter()
+            .filter_map(|ch| self.power.get(ch))
+            .copied()
+            .collect();
+
+        if known.len() < circuit.chargers.len() {
+            return circuit.rated_current;
+        }
+
+        let demand = known.iter().sum::<f64>() * 1000.0 / (3.0 * self.voltage);
+        (demand + self.headroom).min(circuit.rated_current)
+    }
+
+    /// Compute the per-phase current to allocate to each circuit
+    pub fn allocate(&self) -> Vec<Allocation> {
+        let mut remaining = (self.budget_kw * 1000.0 / (3.0 * self.voltage)).max(0.0);
+        let mut result = HashMap::new();
+
+        match &self.strategy {
+            Strategy::Priority(order) => {
+                let mut circuits: Vec<&CircuitBudget> = self.circuits.iter().collect();
+                circuits.sort_by_key(|c| {
+                    order
+                        .iter()
+                        .position(|&id| id == c.id)
+                        .unwrap_or(usize::MAX)
+                });
+                for c in circuits {
+                    let current = self.cap(c).min(remaining);
+                    remaining -= current;
+                    result.insert(c.id, current);
+                }
+            }
+            Strategy::Proportional => {
+                let mut active: Vec<(&CircuitBudget, f64)> =
+                    self.circuits.iter().map(|c| (c, self.cap(c))).collect();
+
+                // Circuits that need less than their share are served first, and
+                // whatever they leave is shared among the others.
+                loop {
+                    let weights: f64 = active.iter().map(|(c, _)| c.rated_current).sum();
+                    if weights <= 0.0 {
+                        break;
+                    }
+                    let (satisfied, rest): (Vec<_>, Vec<_>) = active
+                        .into_iter()
+                        .partition(|(c, cap)| *cap <= remaining * c.rated_current / weights);
+
+                    if satisfied.is_empty() {
+                        for (c, _) in &rest {
+                            result.insert(c.id, remaining * c.rated_current / weights);
+                        }
+                        break;
+                    }
+
+                    for (c, cap) in satisfied {
+                        remaining -= cap;
+                        result.insert(c.id, cap);
+                    }
+                    active = rest;
+                }
+            }
+        }
+
+        self.circuits
+            .iter()
+            .map(|c| Allocation {
+                circuit_id: c.id,
+                current: result.get(&c.id).copied().unwrap_or(0.0),
+            })
+            .collect()
+    }
+
+    /// Allocations that differ from the last applied ones by more than the threshold
+    fn pending(&self) -> impl Iterator<Item = Allocation> + '_ {
+        self.allocate().into_iter().filter(|a| {
+            self.applied
+                .get(&a.circuit_id)
+                .is_none_or(|&prev| (prev - a.current).abs() >= self.threshold)
+        })
+    }
+
+    /// Send the changed allocations to the API
+    pub fn apply(&mut self, ctx: &mut Context) -> Result<(), ApiError> {
+        let pending: Vec<Allocation> = self.pending().collect();
+        if pending.is_empty() {
+            debug!("No change in allocation");
+        }
+        for a in pending {
+            info!("Circuit {}: allocating {:.1}A", a.circuit_id, a.current);
+            ctx.set_circuit_dynamic_current(
+                self.site_id,
+                a.circuit_id,
+                SetCurrent {
+                    time_to_live: self.time_to_live,
+                    current: Triphase::from(Amperes(a.current)),
+                },
+            )?;
+            self.applied.insert(a.circuit_id, a.current);
+        }
+        Ok(())
+    }
+}
+
+/// Current allocated to a charger
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChargerAllocation {
+    pub charger: ChargerId,
+    pub current: f64,
+}
+
+/// Splits the current available on a circuit between its chargers.
+///
+/// Chargers are grouped in priority tiers, 0 being the highest. A tier only
+/// gets current once every charger of the previous tiers has its minimum.
+/// When a tier cannot be fully served, its chargers take turns, moving
+/// forward every time [`ChargerSharing::rotate`] is called. Whatever is left
+/// once the minimums are granted is shared evenly.
+#[derive(Clone, Debug)]
+pub struct ChargerSharing {
+    tiers: HashMap<ChargerId, u8>,
+    turn: usize,
+
+    /// Tier of the chargers not explicitly assigned one
+    pub default_tier: u8,
+
+    /// Minimum current for a charger to be worth serving, in A
+    pub min_current: f64,
+
+    /// Maximum current of a charger, in A
+    pub max_current: f64,
+}
+
+impl Default for ChargerSharing {
+    fn default() -> Self {
+        Self {
+            tiers: HashMap::new(),
+            turn: 0,
+            default_tier: 1,
+            min_current: 6.0,
+            max_current: 32.0,
+        }
+    }
+}
+
+impl ChargerSharing {
+    pub fn set_tier(&mut self, charger: &ChargerId, tier: u8) {
+        self.tiers.insert(charger.clone(), tier);
+    }
+
+    fn tier(&self, charger: &ChargerId) -> u8 {
+        self.tiers
+            .get(charger)
+            .copied()
+            .unwrap_or(self.default_tier)
+    }
+
+    /// Hand the turn over to the next chargers waiting in a partially served tier
+    pub fn rotate(&mut self) {
+        self.turn = self.turn.wrapping_add(1);
+    }
+
+    /// Split `available` amperes between the given chargers
+    pub fn share(&self, available: f64, chargers: &[ChargerId]) -> Vec<ChargerAllocation> {
+        let mut tiers: BTreeMap<u8, Vec<&ChargerId>> = BTreeMap::new();
+        for ch in chargers {
+            tiers.entry(self.tier(ch)).or_default().push(ch);
+        }
+
+        let mut remaining = available.max(0.0);
+        let mut served: Vec<&ChargerId> = vec![];
+        for group in tiers.values() {
+            let fit = ((remaining / self.min_current).floor() as usize).min(group.len());
+            let start = if fit < group.len() {
+                self.turn % group.len()
+            } else {
+                0
+            };
+            served.extend((0..fit).map(|i| group[(start + i) % group.len()]));
+            remaining -= fit as f64 * self.min_current;
+        }
+
+        let extra = if served.is_empty() {
+            0.0
+        } else {
+            (remaining / served.len() as f64).min(self.max_current - self.min_current)
+        };
+
+        chargers
+            .iter()
+            .map(|ch| ChargerAllocation {
+                charger: ch.clone(),
+                current: if served.contains(&ch) {
+                    self.min_current + extra
+                } else {
+                    0.0
+                },
+            })
+            .collect()
+    }
+
+    /// Split `available` amperes between the given chargers, and apply the result
+    pub fn apply(
+        &self,
+        ctx: &mut Context,
+        available: f64,
+        chargers: &[Charger],
+    ) -> Result<Vec<ChargerAllocation>, ApiError> {
+        let ids: Vec<ChargerId> = chargers.iter().map(|c| c.id.clone()).collect();
+        let allocations = self.share(available, &ids);
+        for (charger, a) in chargers.iter().zip(&allocations) {
+            info!("Charger {}: allocating {:.1}A", a.charger, a.current);
+            charger.set_dynamic_current(ctx, a.current)?;
+        }
+        Ok(allocations)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::{BudgetController, ChargerSharing, Strategy};
+    use crate::api::SiteDetails;
+    use crate::ids::{ChargerId, CircuitId};
+
+    fn site() -> SiteDetails {
+        let charger = |id: &str| {
+            json!({ "id": id, "name": id, "productCode": 1, "color": null,
+                    "createdOn": "2024-01-01T00:00:00", "updatedOn": "2024-01-01T00:00:00",
+                    "levelOfAccess": 1 })
+        };
+        let circuit = |id: u32, rated: f64, ch: &str| {
+            json!({ "id": id, "uuid": "", "siteId": 1, "circuitPanelId": 1, "panelName": "",
+                    "ratedCurrent": rated, "fuse": rated, "useDynamicMaster": false,
+                    "chargers": [charger(ch)] })
+        };
+        SiteDetails::deserialize(&json!({
+            "id": 1, "levelOfAccess": 1, "uuid": null, "siteKey": null, "name": null,
+            "installerAlias": null,
+            "circuits": [circuit(10, 32.0, "EH1"), circuit(20, 16.0, "EH2")]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn allocation() {
+        // 33.12 kW is 48A per phase at 230V
+        let mut ctl = BudgetController::new(&site(), 33.12, Strategy::Proportional);
+        let alloc = ctl.allocate();
+        assert!((alloc[0].current - 32.0).abs() < 0.01);
+        assert!((alloc[1].current - 16.0).abs() < 0.01);
+
+        ctl.set_budget(16.56); // 24A
+        ctl.observe_power(&"EH2".parse().unwrap(), 0.0);
+        let alloc = ctl.allocate();
+        assert!((alloc[1].current - 2.0).abs() < 0.01);
+        assert!((alloc[0].current - 22.0).abs() < 0.01);
+
+        let ctl = BudgetController::new(&site(), 16.56, Strategy::Priority(vec![CircuitId(20)]));
+        let alloc = ctl.allocate();
+        assert!((alloc[1].current - 16.0).abs() < 0.01);
+        assert!((alloc[0].current - 8.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn tiers_and_turns() {
+        let mut sharing = ChargerSharing::default();
+        let chargers: Vec<ChargerId> = ["A", "VIP", "B"]
+            .iter()
+            .map(|id| id.parse().unwrap())
+            .collect();
+        sharing.set_tier(&chargers[1], 0);
+
+        let currents = |s: &ChargerSharing, amps| -> Vec<f64> {
+            s.share(amps, &chargers).iter().map(|a| a.current).collect()
+        };
+
+        assert_eq!(currents(&sharing, 5.0), vec![0.0, 0.0, 0.0]);
+        assert_eq!(currents(&sharing, 13.0), vec![6.5, 6.5, 0.0]);
+        sharing.rotate();
+        assert_eq!(currents(&sharing, 13.0), vec![0.0, 6.5, 6.5]);
+        assert_eq!(currents(&sharing, 24.0), vec![8.0, 8.0, 8.0]);
+    }
+}
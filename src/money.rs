@@ -0,0 +1,84 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Exact decimal value of an amount read from the API. JSON amounts are
+/// parsed as `f64`, whose shortest representation gives back the decimal
+/// sent by the server for anything up to 15 significant digits.
+#[cfg(feature = "decimal")]
+pub fn exact(amount: f64) -> Option<rust_decimal::Decimal> {
+    amount.to_string().parse().ok()
+}
+
+/// An amount in a given currency, such as `EUR` or `NOK`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: f64, currency: &str) -> Self {
+        Self {
+            amount,
+            currency: currency.to_owned(),
+        }
+    }
+
+    /// The amount as an exact decimal, see [`exact`]
+    #[cfg(feature = "decimal")]
+    pub fn exact_amount(&self) -> Option<rust_decimal::Decimal> {
+        exact(self.amount)
+    }
+}
+
+/// Round an amount to cents as on an invoice, halves away from zero
+#[cfg(feature = "decimal")]
+pub fn round_cents(amount: rust_decimal::Decimal) -> rust_decimal::Decimal {
+    amount.round_dp_with_strategy(2, rust_decimal::RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Formatted with two decimals, as on an invoice. With the `decimal` feature,
+/// halves are rounded away from zero on the exact amount.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "decimal")]
+        if let Some(amount) = self.exact_amount() {
+            return write!(f, "{:.2} {}", round_cents(amount), self.currency);
+        }
+        write!(f, "{:.2} {}", self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Money;
+
+    #[test]
+    fn invoice_display() {
+        let cost = Money::new(12.5, "EUR");
+        assert_eq!(cost.to_string(), "12.50 EUR");
+        let session: crate::api::ChargingSession = serde_json::from_value(serde_json::json!({
+            "sessionEnergy": 10.0,
+            "costIncludingVat": 2.35,
+            "currencyId": "NOK",
+        }))
+        .unwrap();
+        assert_eq!(session.cost().unwrap().to_string(), "2.35 NOK");
+
+        #[cfg(feature = "decimal")]
+        assert_eq!(
+            session.cost().unwrap().exact_amount(),
+            Some(rust_decimal::Decimal::new(235, 2))
+        );
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn invoice_rounding() {
+        assert_eq!(Money::new(2.675, "EUR").to_string(), "2.68 EUR");
+        assert_eq!(Money::new(1.005, "EUR").to_string(), "1.01 EUR");
+        assert_eq!(Money::new(-1.005, "EUR").to_string(), "-1.01 EUR");
+        assert_eq!(Money::new(7.0, "NOK").to_string(), "7.00 NOK");
+    }
+}
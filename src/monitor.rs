@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
+
+use crate::{
+    api::{ChargerOpMode, UtcDateTime},
+    ids::ChargerId,
+    observation::{Event, Observation},
+};
+
+/// Progress of an ongoing charging session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SessionProgress {
+    pub started: UtcDateTime,
+
+    /// Energy delivered since the start of the session, in kWh
+    pub energy: f64,
+    pub elapsed: Duration,
+
+    /// Average power since the start of the session, in kW
+    pub average_power: f64,
+
+    /// Latest reported power, in kW
+    pub current_power: f64,
+}
+
+impl SessionProgress {
+    /// Time needed to deliver `target` kWh in total, at the current power
+    pub fn remaining(&self, target: f64) -> Option<Duration> {
+        let missing = target - self.energy;
+        if missing <= 0.0 {
+            return Some(Duration::zero());
+        }
+        if self.current_power <= 0.0 {
+            return None;
+        }
+        Duration::try_milliseconds((missing / self.current_power * 3_600_000.0) as i64)
+    }
+
+    /// Estimated time at which `target` kWh will have been delivered
+    pub fn estimated_completion(&self, target: f64, now: UtcDateTime) -> Option<UtcDateTime> {
+        Some(UtcDateTime(now.0 + self.remaining(target)?))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Session {
+    started: UtcDateTime,
+    start_lifetime_energy: Option<f64>,
+    integrated_energy: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Track {
+    op_mode: Option<ChargerOpMode>,
+    power: f64,
+    power_since: Option<UtcDateTime>,
+    lifetime_energy: Option<f64>,
+    session: Option<Session>,
+}
+
+impl Track {
+    fn session_energy(&self, session: &Session, now: UtcDateTime) -> f64 {
+        match (session.start_lifetime_energy, self.lifetime_energy) {
+            (Some(start), Some(current)) => current - start,
+            _ => {
+                let pending = self.power_since.map_or(0.0, |since| {
+                    self.power * (now.0 - since.0).num_milliseconds() as f64 / 3_600_000.0
+                });
+                session.integrated_energy + pending
+            }
+        }
+    }
+}
+
+/// Keeps track of the status of chargers from the observation stream
+#[derive(Clone, Debug, Default)]
+pub struct ChargerMonitor {
+    chargers: HashMap<ChargerId, Track>,
+}
+
+impl ChargerMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed an event from the observation stream
+    pub fn observe(&mut self, event: &Event, now: UtcDateTime) {
+        let track = self.chargers.entry(event.charger.clone()).or_default();
+        match event.observation {
+            Observation::ChargerOpMode(mode) => {
+                track.op_mode = Some(mode);
+                match mode {
+                    ChargerOpMode::Charging if track.session.is_none() => {
+                        track.session = Some(Session {
+                            started: now,
+                            start_lifetime_energy: track.lifetime_energy,
+                            integrated_energy: 0.0,
+                        });
+                    }
+                    ChargerOpMode::Disconnected => track.session = None,
+                    _ => (),
+                }
+            }
+            Observation::TotalPower(kw) => {
+                if let (Some(since), Some(session)) = (track.power_since, &mut track.session) {
+                    let hours = (now.0 - since.0).num_milliseconds() as f64 / 3_600_000.0;
+                    session.integrated_energy += track.power * hours;
+                }
+                track.power = kw;
+                track.power_since = Some(now);
+            }
+            Observation::LifetimeEnergy(kwh) => {
+                track.lifetime_energy = Some(kwh);
+                if let Some(session) = &mut track.session {
+                    session.start_lifetime_energy.get_or_insert(kwh);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn op_mode(&self, charger: &str) -> Option<ChargerOpMode> {
+        self.chargers.get(charger)?.op_mode
+    }
+
+    /// Latest reported power, in kW
+    pub fn power(&self, charger: &str) -> Option<f64> {
+        Some(self.chargers.get(charger)?.power)
+    }
+
+    /// Progress of the ongoing session of a charger, if any
+    pub fn progress(&self, charger: &str, now: UtcDateTime) -> Option<SessionProgress> {
+        let track = self.chargers.get(charger)?;
+        let session = track.session.as_ref()?;
+        let energy = track.session_energy(session, now);
+        let elapsed = now.0 - session.started.0;
+        let hours = elapsed.num_milliseconds() as f64 / 3_600_000.0;
+        Some(SessionProgress {
+            started: session.started,
+            energy,
+            elapsed,
+            average_power: if hours > 0.0 { energy / hours } else { 0.0 },
+            current_power: track.power,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::ChargerMonitor;
+    use crate::{
+        api::{ChargerOpMode, UtcDateTime},
+        observation::{Event, Observation},
+    };
+
+    #[test]
+    fn session_progress() {
+        let t0 = UtcDateTime(Utc.with_ymd_and_hms(2024, 1, 1, 18, 0, 0).unwrap());
+        let at = |minutes| UtcDateTime(t0.0 + Duration::minutes(minutes));
+        let event = |observation| Event {
+            charger: "EH1".parse().unwrap(),
+            observation,
+        };
+
+        let mut monitor = ChargerMonitor::new();
+        monitor.observe(
+            &event(Observation::ChargerOpMode(ChargerOpMode::Charging)),
+            at(0),
+        );
+        monitor.observe(&event(Observation::TotalPower(11.0)), at(0));
+        monitor.observe(&event(Observation::TotalPower(7.0)), at(30));
+
+        let progress = monitor.progress("EH1", at(60)).unwrap();
+        assert!((progress.energy - 9.0).abs() < 1e-9);
+        assert!((progress.average_power - 9.0).abs() < 1e-9);
+        assert_eq!(progress.remaining(16.0), Some(Duration::hours(1)));
+        assert_eq!(progress.estimated_completion(16.0, at(60)), Some(at(120)));
+
+        monitor.observe(
+            &event(Observation::ChargerOpMode(ChargerOpMode::Disconnected)),
+            at(61),
+        );
+        assert!(monitor.progress("EH1", at(61)).is_none());
+    }
+}
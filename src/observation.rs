@@ -1,51 +1,96 @@
-use serde::{de::{DeserializeOwned, IntoDeserializer}, Deserialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_repr::Deserialize_repr;
 use std::num::{ParseFloatError, ParseIntError};
 use thiserror::Error;
-use tracing::info;
+
+pub use crate::api::PhaseMode;
+
+use crate::api::{ChargerOpMode, CommandReply, OutputPhase, UtcDateTime};
+use crate::ids::ChargerId;
+
+#[cfg(feature = "tungstenite")]
+use std::{collections::{HashMap, VecDeque}, io::{Read, Write}, net::TcpStream, time::{Duration, Instant}};
+#[cfg(feature = "tungstenite")]
+use tungstenite::stream::MaybeTlsStream;
+#[cfg(feature = "tungstenite")]
+use tracing::{debug, info};
+#[cfg(feature = "tungstenite")]
 use ureq::json;
 
+#[cfg(feature = "tungstenite")]
 use crate::{
-    api::{ChargerOpMode, Context, OutputPhase, UtcDateTime},
+    api::{ApiError, Context},
+    fleet::Command,
     signalr::{self, StreamError},
     stream::NegotiateError,
 };
 
-#[derive(Clone, Copy, Debug, Deserialize_repr)]
-#[repr(u8)]
+/// State of the control pilot, as a letter of IEC 61851. Other letters are
+/// kept as `Unknown`, with 0 for values that aren't a single letter.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(from = "u8", into = "u8")]
+#[non_exhaustive]
 pub enum PilotMode {
-    Disconnected = b'A',
-    Connected = b'B',
-    Charging = b'C',
-    NeedsVentilation = b'D',
-    FaultDetected = b'F',
-    Unknown = b'\x00',
+    Disconnected,
+    Connected,
+    Charging,
+    NeedsVentilation,
+    FaultDetected,
+    Unknown(u8),
 }
 
-impl From<&str> for PilotMode {
-    fn from(value: &str) -> Self {
+impl From<u8> for PilotMode {
+    fn from(value: u8) -> Self {
         use PilotMode::*;
         match value {
-            "A" => Disconnected,
-            "B" => Connected,
-            "C" => Charging,
-            "D" => NeedsVentilation,
-            "F" => FaultDetected,
-            _ => Unknown,
+            b'A' => Disconnected,
+            b'B' => Connected,
+            b'C' => Charging,
+            b'D' => NeedsVentilation,
+            b'F' => FaultDetected,
+            other => Unknown(other),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize_repr)]
-#[repr(u8)]
-pub enum PhaseMode {
-    Ignore = 0,
-    Phase1 = 1,
-    Auto = 2,
-    Phase2 = 3,
+impl From<PilotMode> for u8 {
+    fn from(mode: PilotMode) -> Self {
+        use PilotMode::*;
+        match mode {
+            Disconnected => b'A',
+            Connected => b'B',
+            Charging => b'C',
+            NeedsVentilation => b'D',
+            FaultDetected => b'F',
+            Unknown(other) => other,
+        }
+    }
+}
+
+impl std::fmt::Display for PilotMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use PilotMode::*;
+        f.write_str(match self {
+            Disconnected => "Disconnected",
+            Connected => "Connected",
+            Charging => "Charging",
+            NeedsVentilation => "Needs ventilation",
+            FaultDetected => "Fault detected",
+            Unknown(other) => return write!(f, "Pilot mode {other}"),
+        })
+    }
+}
+
+impl From<&str> for PilotMode {
+    fn from(value: &str) -> Self {
+        match value.as_bytes() {
+            [letter] => PilotMode::from(*letter),
+            _ => PilotMode::Unknown(0),
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
 pub enum InputPin {
     T1,
     T2,
@@ -63,7 +108,8 @@ enum DataType {
     String = 6,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
 pub enum ObservationData {
     Boolean(bool),
     Double(f64),
@@ -78,6 +124,9 @@ pub enum ParseError {
 
     #[error("double `{0}: {1}")]
     Double(String, ParseFloatError),
+
+    #[error("product ID `{0}`")]
+    ProductId(String),
 }
 
 impl ObservationData {
@@ -119,53 +168,246 @@ impl ObservationData {
     */
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct ReasonForNoCurrent(u16);
+/// Historical observations carry plain JSON values rather than the
+/// string-encoded values of the stream.
+impl<'de> Deserialize<'de> for ObservationData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        use serde_json::Value;
+        Ok(match Value::deserialize(deserializer)? {
+            Value::Bool(b) => ObservationData::Boolean(b),
+            Value::String(s) => ObservationData::String(s),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => ObservationData::Integer(i),
+                None => ObservationData::Double(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            other => return Err(D::Error::custom(format!("unexpected observation value {other}"))),
+        })
+    }
+}
+
+/// A past value of an observation
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObservationSample {
+    pub timestamp: UtcDateTime,
+    pub value: ObservationData,
+}
+
+impl ObservationSample {
+    /// Decode the sample, given the ID of the observation it was requested for.
+    /// Whole numbers are read back as doubles for the observations streamed
+    /// as doubles, as their JSON values lose the distinction.
+    pub fn observation(&self, observation_id: u16) -> Observation {
+        match Observation::try_from_data(observation_id, self.value.clone()) {
+            unknown @ Observation::Unknown {
+                value: ObservationData::Integer(i),
+                ..
+            } => {
+                let double = ObservationData::Double(i as f64);
+                match Observation::try_from_data(observation_id, double) {
+                    Observation::Unknown { .. } => unknown,
+                    observation => observation,
+                }
+            }
+            observation => observation,
+        }
+    }
+}
+
+/// Why a charger doesn't deliver current, as reported by observation 96 and
+/// by the charger state. Codes without a known meaning are kept as `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(from = "u16", into = "u16")]
+#[non_exhaustive]
+pub enum ReasonForNoCurrent {
+    /// The charger is free to deliver current
+    Ok,
+    LoadBalancing(LoadBalancingReason),
+    Error(ErrorReason),
+    Waiting(WaitingReason),
+    Limit(LimitReason),
+    Unknown(u16),
+}
+
+/// Kind of a [`ReasonForNoCurrent`], for alerting on whole groups of reasons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReasonCategory {
+    Ok,
+    LoadBalancing,
+    Error,
+    Waiting,
+    Limit,
+    Unknown,
+}
+
+/// Current held back by the load balancing of the circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LoadBalancingReason {
+    CircuitTooLow = 1,
+    DynamicCircuitTooLow = 2,
+    MaxDynamicOffline = 3,
+    CircuitFuseTooLow = 4,
+    WaitingInQueue = 5,
+    WaitingInChargedQueue = 6,
+}
+
+/// Current prevented by a fault of the installation, charger or car
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorReason {
+    IllegalGridType = 7,
+    NoRequestFromCar = 8,
+    MasterCommunicationLost = 9,
+    NoCurrentFromEqualizer = 10,
+    PhaseDisconnected = 11,
+    ChargerDisabled = 53,
+    ChargerInErrorState = 56,
+    ErraticEv = 57,
+    Undefined = 100,
+}
+
+/// Current withheld until a condition is met
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WaitingReason {
+    PendingSchedule = 54,
+    PendingAuthorization = 55,
+}
+
+/// Current capped by a setting or by the hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LimitReason {
+    CircuitFuse = 25,
+    CircuitMaxCurrent = 26,
+    DynamicCircuitCurrent = 27,
+    Equalizer = 28,
+    CircuitLoadBalancing = 29,
+    OfflineSettings = 30,
+    CableRating = 75,
+    Schedule = 76,
+    ChargerMaxCurrent = 77,
+    DynamicChargerCurrent = 78,
+    CarNotCharging = 79,
+    LocalAdjustment = 80,
+    Car = 81,
+}
+
+impl ReasonForNoCurrent {
+    pub fn category(&self) -> ReasonCategory {
+        match self {
+            ReasonForNoCurrent::Ok => ReasonCategory::Ok,
+            ReasonForNoCurrent::LoadBalancing(_) => ReasonCategory::LoadBalancing,
+            ReasonForNoCurrent::Error(_) => ReasonCategory::Error,
+            ReasonForNoCurrent::Waiting(_) => ReasonCategory::Waiting,
+            ReasonForNoCurrent::Limit(_) => ReasonCategory::Limit,
+            ReasonForNoCurrent::Unknown(_) => ReasonCategory::Unknown,
+        }
+    }
+}
+
+impl From<u16> for ReasonForNoCurrent {
+    fn from(code: u16) -> Self {
+        use ErrorReason as E;
+        use LimitReason as L;
+        use LoadBalancingReason as B;
+        use ReasonForNoCurrent::{Error, Limit, LoadBalancing, Unknown, Waiting};
+        use WaitingReason as W;
+        match code {
+            0 => ReasonForNoCurrent::Ok,
+            1 => LoadBalancing(B::CircuitTooLow),
+            2 => LoadBalancing(B::DynamicCircuitTooLow),
+            3 => LoadBalancing(B::MaxDynamicOffline),
+            4 => LoadBalancing(B::CircuitFuseTooLow),
+            5 => LoadBalancing(B::WaitingInQueue),
+            6 => LoadBalancing(B::WaitingInChargedQueue),
+            7 => Error(E::IllegalGridType),
+            8 => Error(E::NoRequestFromCar),
+            9 => Error(E::MasterCommunicationLost),
+            10 => Error(E::NoCurrentFromEqualizer),
+            11 => Error(E::PhaseDisconnected),
+            25 => Limit(L::CircuitFuse),
+            26 => Limit(L::CircuitMaxCurrent),
+            27 => Limit(L::DynamicCircuitCurrent),
+            28 => Limit(L::Equalizer),
+            29 => Limit(L::CircuitLoadBalancing),
+            30 => Limit(L::OfflineSettings),
+            53 => Error(E::ChargerDisabled),
+            54 => Waiting(W::PendingSchedule),
+            55 => Waiting(W::PendingAuthorization),
+            56 => Error(E::ChargerInErrorState),
+            57 => Error(E::ErraticEv),
+            75 => Limit(L::CableRating),
+            76 => Limit(L::Schedule),
+            77 => Limit(L::ChargerMaxCurrent),
+            78 => Limit(L::DynamicChargerCurrent),
+            79 => Limit(L::CarNotCharging),
+            80 => Limit(L::LocalAdjustment),
+            81 => Limit(L::Car),
+            100 => Error(E::Undefined),
+            other => Unknown(other),
+        }
+    }
+}
+
+impl From<ReasonForNoCurrent> for u16 {
+    fn from(reason: ReasonForNoCurrent) -> Self {
+        match reason {
+            ReasonForNoCurrent::Ok => 0,
+            ReasonForNoCurrent::LoadBalancing(r) => r as u16,
+            ReasonForNoCurrent::Error(r) => r as u16,
+            ReasonForNoCurrent::Waiting(r) => r as u16,
+            ReasonForNoCurrent::Limit(r) => r as u16,
+            ReasonForNoCurrent::Unknown(other) => other,
+        }
+    }
+}
 
 impl std::fmt::Display for ReasonForNoCurrent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self.0 {
-                0 => "OK",
-                1 => "LoadBalance: circuit too low",
-                2 => "LoadBalance: dynamic circuit too low",
-                3 => "LoadBalance: max dynamic offline",
-                4 => "LoadBalance: circuit fuse too low",
-                5 => "LoadBalance: waiting in queue",
-                6 => "LoadBalance: waiting in charged queue",
-                7 => "Error: illegal grid type",
-                8 => "Error: not received request from car",
-                9 => "Error: master communication lost",
-                10 => "Error: no current from equalizer",
-                11 => "Error: no current, phase disconnected",
-                25 => "Error: limited by circuit fuse",
-                26 => "Error: limited by circuit max current",
-                27 => "Error: limited by dynamic circuit current",
-                28 => "Error: limited by equalizer",
-                29 => "Error: limited by circuit load balancing",
-                30 => "Error: limited by offline settings",
-                53 => "Info: charger disabled",
-                54 => "Waiting: pending schedule",
-                55 => "Waiting: pending authorization",
-                56 => "Error: charger in error state",
-                57 => "Error: Erratic EV",
-                75 => "Cable: limited by cable rating",
-                76 => "Schedule: limited by schedule",
-                77 => "Charger limit: limited by charger max current",
-                78 => "Charger Limit: limited by dynamic charger current",
-                79 => "Car limit: limited by car not charging",
-                80 => "Local: limited by local adjustment",
-                81 => "Car limit: limited by car",
-                100 => "Error: undefined",
-                other => return write!(f, "Code {other}"),
-            }
-        )
+        use ErrorReason as E;
+        use LimitReason as L;
+        use LoadBalancingReason as B;
+        use ReasonForNoCurrent::{Error, Limit, LoadBalancing, Unknown, Waiting};
+        use WaitingReason as W;
+        let text = match self {
+            ReasonForNoCurrent::Ok => "OK",
+            LoadBalancing(B::CircuitTooLow) => "LoadBalance: circuit too low",
+            LoadBalancing(B::DynamicCircuitTooLow) => "LoadBalance: dynamic circuit too low",
+            LoadBalancing(B::MaxDynamicOffline) => "LoadBalance: max dynamic offline",
+            LoadBalancing(B::CircuitFuseTooLow) => "LoadBalance: circuit fuse too low",
+            LoadBalancing(B::WaitingInQueue) => "LoadBalance: waiting in queue",
+            LoadBalancing(B::WaitingInChargedQueue) => "LoadBalance: waiting in charged queue",
+            Error(E::IllegalGridType) => "Error: illegal grid type",
+            Error(E::NoRequestFromCar) => "Error: not received request from car",
+            Error(E::MasterCommunicationLost) => "Error: master communication lost",
+            Error(E::NoCurrentFromEqualizer) => "Error: no current from equalizer",
+            Error(E::PhaseDisconnected) => "Error: no current, phase disconnected",
+            Limit(L::CircuitFuse) => "Error: limited by circuit fuse",
+            Limit(L::CircuitMaxCurrent) => "Error: limited by circuit max current",
+            Limit(L::DynamicCircuitCurrent) => "Error: limited by dynamic circuit current",
+            Limit(L::Equalizer) => "Error: limited by equalizer",
+            Limit(L::CircuitLoadBalancing) => "Error: limited by circuit load balancing",
+            Limit(L::OfflineSettings) => "Error: limited by offline settings",
+            Error(E::ChargerDisabled) => "Info: charger disabled",
+            Waiting(W::PendingSchedule) => "Waiting: pending schedule",
+            Waiting(W::PendingAuthorization) => "Waiting: pending authorization",
+            Error(E::ChargerInErrorState) => "Error: charger in error state",
+            Error(E::ErraticEv) => "Error: Erratic EV",
+            Limit(L::CableRating) => "Cable: limited by cable rating",
+            Limit(L::Schedule) => "Schedule: limited by schedule",
+            Limit(L::ChargerMaxCurrent) => "Charger limit: limited by charger max current",
+            Limit(L::DynamicChargerCurrent) => "Charger Limit: limited by dynamic charger current",
+            Limit(L::CarNotCharging) => "Car limit: limited by car not charging",
+            Limit(L::LocalAdjustment) => "Local: limited by local adjustment",
+            Limit(L::Car) => "Car limit: limited by car",
+            Error(E::Undefined) => "Error: undefined",
+            Unknown(other) => return write!(f, "Code {other}"),
+        };
+        f.write_str(text)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "value")]
 pub enum Observation {
     SelfTestResult(String),
     SelfTestDetails(String),
@@ -211,23 +453,6 @@ pub enum Observation {
     Unknown { code: u16, value: ObservationData },
 }
 
-fn op_mode_from_int(mode: i64) -> ChargerOpMode {
-    use ChargerOpMode::*;
-    match mode {
-        1 => Disconnected,
-        2 => Paused,
-        3 => Charging,
-        4 => Finished,
-        5 => Error,
-        6 => Ready,
-        _ => Unknown,
-    }
-}
-
-fn deserialize_i64<T: DeserializeOwned>(value: i64) -> Option<T> {
-    T::deserialize(<i64 as IntoDeserializer<serde::de::value::Error>>::into_deserializer(value)).ok()
-}
-
 impl Observation {
     fn try_from_data(code: u16, data: ObservationData) -> Observation {
         use InputPin::*;
@@ -252,14 +477,14 @@ impl Observation {
             (75, Double(amperes)) => CircuitTotalCurrent { phase: 3, amperes },
             (81, String(iccid)) => ICCID(iccid),
             (84, String(operator)) => MobileNetworkOperator(operator),
-            (96, Integer(reason)) => ReasonForNoCurrent(self::ReasonForNoCurrent(reason as u16)),
+            (96, Integer(reason)) => ReasonForNoCurrent((reason as u16).into()),
             (100, String(l)) => PilotMode(super::observation::PilotMode::from(&*l)),
             (102, Boolean(enabled)) => SmartCharging(enabled),
             (103, Boolean(locked)) => CableLocked(locked),
             (104, Double(amps)) => CableRating(amps),
             (107, String(tok_rev)) => UserId(tok_rev.chars().rev().collect()),
-            (109, Integer(mode)) => ChargerOpMode(op_mode_from_int(mode)),
-            (110, Integer(mode)) => ActiveOutputPhase(deserialize_i64(mode).unwrap_or(OutputPhase::Unknown)),
+            (109, Integer(mode @ 0..=255)) => ChargerOpMode(self::ChargerOpMode::from(mode as u8)),
+            (110, Integer(mode @ 0..=255)) => ActiveOutputPhase(OutputPhase::from(mode as u8)),
             (120, Double(power)) => TotalPower(power),
             (122, Double(energy)) => EnergyPerHour(energy),
             (124, Double(energy)) => LifetimeEnergy(energy),
@@ -287,16 +512,169 @@ impl Observation {
     }
 }
 
+/// Codes of commonly used observations
+pub mod ids {
+    pub const IS_ENABLED: u16 = 31;
+    pub const DYNAMIC_CHARGER_CURRENT: u16 = 48;
+    pub const REASON_FOR_NO_CURRENT: u16 = 96;
+    pub const SMART_CHARGING: u16 = 102;
+    pub const CABLE_LOCKED: u16 = 103;
+    pub const CHARGER_OP_MODE: u16 = 109;
+    pub const OUTPUT_PHASE: u16 = 110;
+    pub const TOTAL_POWER: u16 = 120;
+    pub const ENERGY_PER_HOUR: u16 = 122;
+    pub const LIFETIME_ENERGY: u16 = 124;
+}
+
+impl Observation {
+    /// Code of the observation in the Easee protocol, as used by [`ids`]
+    pub fn code(&self) -> Option<u16> {
+        use InputPin::*;
+        use Observation::*;
+        let code = match self {
+            SelfTestResult(_) => 1,
+            SelfTestDetails(_) => 2,
+            WifiEvent(_) => 10,
+            ChargerOfflineReason(_) => 11,
+            CircuitMaxCurrent { phase, .. } => 21 + *phase as u16,
+            SiteID(_) => 26,
+            IsEnabled(_) => 31,
+            Temperature(_) => 32,
+            TriplePhase(_) => 38,
+            DynamicChargerCurrent(_) => 48,
+            CircuitTotalCurrent { phase, .. } => 72 + *phase as u16,
+            ICCID(_) => 81,
+            MobileNetworkOperator(_) => 84,
+            ReasonForNoCurrent(_) => 96,
+            PilotMode(_) => 100,
+            SmartCharging(_) => 102,
+            CableLocked(_) => 103,
+            CableRating(_) => 104,
+            UserId(_) => 107,
+            ChargerOpMode(_) => 109,
+            ActiveOutputPhase(_) => 110,
+            TotalPower(_) => 120,
+            EnergyPerHour(_) => 122,
+            LifetimeEnergy(_) => 124,
+            LifetimeRelaySwitches(_) => 125,
+            LifetimeHours(_) => 126,
+            MaximumTemperature(_) => 150,
+            IntCurrent { pin, .. } => match pin {
+                T2 => 182,
+                T3 => 183,
+                T4 => 184,
+                T5 => 185,
+                T1 => return None,
+            },
+            IntVoltage { pins, .. } => match pins {
+                (T1, T2) => 190,
+                (T1, T3) => 191,
+                (T1, T4) => 192,
+                (T1, T5) => 193,
+                (T2, T3) => 194,
+                (T2, T4) => 195,
+                (T2, T5) => 196,
+                (T3, T4) => 197,
+                (T3, T5) => 198,
+                (T4, T5) => 199,
+                _ => return None,
+            },
+            Unknown { code, .. } => *code,
+            LocalPreauthEnabled(_) | LocalOfflineAuthEnabled(_) | AllowOfflineTxUnknownId(_) => return None,
+        };
+        Some(code)
+    }
+}
+
 #[derive(Debug)]
 pub struct Event {
-    pub charger: String,
+    pub charger: ChargerId,
     pub observation: Observation,
 }
 
-pub struct Stream {
-    inner: signalr::Stream,
+/// Version of the [`EventEnvelope`] format. Bumped on incompatible changes;
+/// new observation types may appear without a bump.
+pub const EVENT_SCHEMA: u32 = 1;
+
+/// Where an event was read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSource {
+    Stream,
+    Rest,
+}
+
+/// Stable serialized form of an [`Event`], for export to other systems
+#[derive(Debug, Serialize)]
+pub struct EventEnvelope<'a> {
+    pub schema: u32,
+    pub emitted_at: UtcDateTime,
+    pub source: EventSource,
+    pub charger: &'a str,
+    pub observation: &'a Observation,
+}
+
+impl Event {
+    pub fn envelope(&self, source: EventSource, emitted_at: UtcDateTime) -> EventEnvelope<'_> {
+        EventEnvelope {
+            schema: EVENT_SCHEMA,
+            emitted_at,
+            source,
+            charger: &self.charger,
+            observation: &self.observation,
+        }
+    }
+}
+
+/// Outcome of a command, as reported by the device on the stream. The
+/// PascalCase keys sent by some stream servers are accepted too.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandResponse {
+    #[serde(alias = "SerialNumber")]
+    pub serial_number: String,
+    #[serde(alias = "Id")]
+    pub id: u64,
+    #[serde(alias = "Ticks")]
+    pub ticks: u64,
+    #[serde(alias = "WasAccepted")]
+    pub was_accepted: bool,
+    #[serde(alias = "ResultCode")]
+    pub result_code: i32,
+    #[serde(default, alias = "Comment")]
+    pub comment: Option<String>,
+    #[serde(default, alias = "DeliveredAt")]
+    pub delivered_at: Option<UtcDateTime>,
 }
 
+impl CommandResponse {
+    /// Whether this responds to the command acknowledged by `reply`
+    pub fn matches(&self, reply: &CommandReply) -> bool {
+        self.serial_number == reply.device && self.ticks == reply.ticks
+    }
+}
+
+#[cfg(feature = "tungstenite")]
+enum Received {
+    Event(Event),
+
+    /// A command response, along with the raw invocation argument
+    CommandResponse(CommandResponse, serde_json::Value),
+}
+
+/// Callback for a SignalR invocation, receiving the target and its arguments
+#[cfg(feature = "tungstenite")]
+pub type InvocationHandler = Box<dyn FnMut(&str, &[serde_json::Value]) + Send>;
+
+#[cfg(feature = "tungstenite")]
+pub struct Stream<S = MaybeTlsStream<TcpStream>> {
+    inner: signalr::Stream<S>,
+    handlers: HashMap<String, InvocationHandler>,
+    fallback: Option<InvocationHandler>,
+    backlog: VecDeque<Event>,
+}
+
+#[cfg(feature = "tungstenite")]
 #[derive(Debug, Error)]
 pub enum ObservationError {
     #[error("stream: {0}")]
@@ -310,52 +688,247 @@ pub enum ObservationError {
 
     #[error("Parsing: {0}")]
     Parsing(#[from] ParseError),
+
+    #[error("api: {0}")]
+    Api(#[source] Box<ApiError>),
+
+    #[error("No response to the command")]
+    Timeout,
+
+    #[error("The command was accepted without a reply to track it")]
+    NoCommandReply,
+}
+
+#[cfg(feature = "tungstenite")]
+impl From<ApiError> for ObservationError {
+    fn from(e: ApiError) -> Self {
+        ObservationError::Api(Box::new(e))
+    }
+}
+
+#[cfg(feature = "tungstenite")]
+impl ObservationError {
+    /// Stable identifier of the kind of error, see [`crate::api::ApiError::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            ObservationError::Stream(e) => e.code(),
+            ObservationError::Protocol(_) => "observation.protocol",
+            ObservationError::Deserialize(_) => "observation.json",
+            ObservationError::Parsing(_) => "observation.value",
+            ObservationError::Api(e) => e.code(),
+            ObservationError::Timeout => "observation.timeout",
+            ObservationError::NoCommandReply => "observation.no_command_reply",
+        }
+    }
+
+    /// Whether nothing was received before the read timeout set with
+    /// [`Stream::set_read_timeout`]. The stream can still be read from.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ObservationError::Stream(e) if e.is_timeout())
+    }
 }
 
+/// An observation with its value encoded as a string, as sent by the stream
+/// and by the REST snapshot of the charger state. The stream sometimes sends
+/// PascalCase keys, the REST API camelCase ones.
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct ProductUpdate {
+pub(crate) struct ProductUpdate {
+    #[serde(alias = "DataType")]
     data_type: DataType,
+    #[serde(alias = "Id")]
     id: u16,
-    mid: String,
-    timestamp: UtcDateTime,
+    #[serde(default, alias = "Mid")]
+    pub(crate) mid: String,
+    #[serde(default, alias = "Timestamp")]
+    timestamp: Option<UtcDateTime>,
+    #[serde(alias = "Value")]
     value: String,
 }
 
+#[cfg(feature = "tungstenite")]
 impl Stream {
     pub fn from_context(ctx: &mut Context) -> Result<Self, NegotiateError> {
-        Ok(Self {
-            inner: signalr::Stream::from_ws(crate::stream::Stream::open(ctx)?),
-        })
+        Ok(Self::from_ws(crate::stream::Stream::open(ctx)?))
+    }
+
+    /// Make [`Stream::recv`] give up after `timeout` without any message,
+    /// see [`ObservationError::is_timeout`]
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
+
+#[cfg(feature = "tungstenite")]
+impl<S: Read + Write> Stream<S> {
+    /// Connect over a transport established by the caller, see [`crate::stream::Stream::open_with`]
+    pub fn from_transport(ctx: &mut Context, transport: S) -> Result<Self, NegotiateError> {
+        Ok(Self::from_ws(crate::stream::Stream::open_with(ctx, transport)?))
+    }
+
+    fn from_ws(ws: crate::stream::Stream<S>) -> Self {
+        Self {
+            inner: signalr::Stream::from_ws(ws),
+            handlers: HashMap::new(),
+            fallback: None,
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Dispatch invocations of `target` to `handler`. Registered handlers take
+    /// precedence over the built-in decoding of `ProductUpdate`. Invocations of
+    /// `CommandResponse` are passed to the handler and still decoded, so that
+    /// [`Self::send_and_confirm`] keeps working.
+    pub fn on_invocation<F>(&mut self, target: &str, handler: F)
+    where
+        F: FnMut(&str, &[serde_json::Value]) + Send + 'static,
+    {
+        self.handlers.insert(target.to_owned(), Box::new(handler));
+    }
+
+    /// Dispatch invocations of targets without a registered handler to `handler`,
+    /// instead of skipping them. Command responses no call waited for are
+    /// dispatched to it too.
+    pub fn on_unknown_invocation<F>(&mut self, handler: F)
+    where
+        F: FnMut(&str, &[serde_json::Value]) + Send + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
     }
 
     pub fn recv(&mut self) -> Result<Event, ObservationError> {
+        if let Some(event) = self.backlog.pop_front() {
+            return Ok(event);
+        }
+        loop {
+            match self.next()? {
+                Received::Event(event) => return Ok(event),
+                Received::CommandResponse(resp, raw) => self.unsolicited(resp, raw),
+            }
+        }
+    }
+
+    /// Send a command through the REST API, and wait for the device to report
+    /// on the stream whether it accepted it. The charger must have been
+    /// subscribed to. Events received in the meantime are kept for [`Self::recv`].
+    ///
+    /// The timeout is only checked when a message arrives, which the periodic
+    /// pings of the server guarantee.
+    pub fn send_and_confirm(
+        &mut self,
+        ctx: &mut Context,
+        charger_id: &ChargerId,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<CommandResponse, ObservationError> {
+        let deadline = Instant::now() + timeout;
+        let reply = ctx
+            .charger_command(charger_id, command.path())?
+            .ok_or(ObservationError::NoCommandReply)?;
+        while Instant::now() < deadline {
+            match self.next()? {
+                Received::Event(event) => self.backlog.push_back(event),
+                Received::CommandResponse(resp, _) if resp.matches(&reply) => return Ok(resp),
+                Received::CommandResponse(resp, raw) => self.unsolicited(resp, raw),
+            }
+        }
+        Err(ObservationError::Timeout)
+    }
+
+    /// Read the stream until the charger reports the `target` operating mode.
+    /// Returns whether it did before `timeout` expired. Events already kept
+    /// for [`Self::recv`], such as by [`Self::send_and_confirm`], are looked at
+    /// first. All events, including the matching one, are kept for
+    /// [`Self::recv`]. The charger must have been subscribed to.
+    pub fn wait_for_op_mode(
+        &mut self,
+        charger_id: &ChargerId,
+        target: ChargerOpMode,
+        timeout: Duration,
+    ) -> Result<bool, ObservationError> {
+        let is_target = |event: &Event| {
+            event.charger == *charger_id
+                && matches!(event.observation, Observation::ChargerOpMode(mode) if mode == target)
+        };
+        if self.backlog.iter().any(is_target) {
+            return Ok(true);
+        }
+
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            match self.next()? {
+                Received::Event(event) => {
+                    let found = is_target(&event);
+                    self.backlog.push_back(event);
+                    if found {
+                        return Ok(true);
+                    }
+                }
+                Received::CommandResponse(resp, raw) => self.unsolicited(resp, raw),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Pass a command response no call waited for to the fallback handler,
+    /// unless a handler registered for them already received it
+    fn unsolicited(&mut self, resp: CommandResponse, raw: serde_json::Value) {
+        if self.handlers.contains_key("CommandResponse") {
+            return;
+        }
+        match &mut self.fallback {
+            Some(handler) => handler("CommandResponse", &[raw]),
+            None => debug!("Unsolicited command response: {resp:?}"),
+        }
+    }
+
+    fn next(&mut self) -> Result<Received, ObservationError> {
         use signalr::Message::*;
-        let de = |msg| -> Result<Event, ObservationError> { Err(ObservationError::Protocol(msg)) };
+        let de = |msg| -> Result<Received, ObservationError> { Err(ObservationError::Protocol(msg)) };
         loop {
             let msg = self.inner.recv()?;
             match &msg {
                 Ping => continue,
                 Empty | InvocationResult { .. } => info!("Skipped message: {msg:?}"),
+                Invocation { target, arguments } if target == "CommandResponse" => {
+                    if arguments.len() != 1 {
+                        return de(msg);
+                    };
+                    if let Some(handler) = self.handlers.get_mut(target) {
+                        handler(target, arguments);
+                    }
+                    let resp = CommandResponse::deserialize(&arguments[0])?;
+                    return Ok(Received::CommandResponse(resp, arguments[0].clone()));
+                }
+                Invocation { target, arguments } if self.handlers.contains_key(target) => {
+                    (self.handlers.get_mut(target).unwrap())(target, arguments)
+                }
                 Invocation { target, arguments } if target == "ProductUpdate" => {
                     if arguments.len() != 1 {
                         return de(msg);
                     };
                     let evt = ProductUpdate::deserialize(&arguments[0])?;
-                    return decode_update(evt);
+                    return Ok(Received::Event(decode_update(evt)?));
                 }
-                Invocation { .. } => continue,
+                Invocation { target, arguments } => match &mut self.fallback {
+                    Some(handler) => handler(target, arguments),
+                    None => debug!("Skipped invocation of {target}"),
+                },
                 _other => return de(msg),
             }
         }
     }
+    pub fn close(&mut self) -> Result<(), ObservationError> {
+        Ok(self.inner.close()?)
+    }
+
     pub fn subscribe(&mut self, id: &str) -> Result<(), tungstenite::Error> {
         self.inner
             .invoke("SubscribeWithCurrentState", json!([id, true]))
     }
 }
 
-fn decode_update(update: ProductUpdate) -> Result<Event, ObservationError> {
+pub(crate) fn decode_update(update: ProductUpdate) -> Result<Event, ParseError> {
     let ProductUpdate {
         data_type,
         id,
@@ -366,8 +939,131 @@ fn decode_update(update: ProductUpdate) -> Result<Event, ObservationError> {
     let data = ObservationData::from_dynamic(value, data_type)?;
     let obs = Observation::try_from_data(id, data);
     let _ = timestamp;
+    let charger = mid.parse().map_err(|_| ParseError::ProductId(mid))?;
     Ok(Event {
-        charger: mid,
+        charger,
         observation: obs,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::{
+        decode_update, ChargerOpMode, CommandResponse, Event, EventSource, LimitReason,
+        Observation, ObservationData, ObservationSample, ProductUpdate, ReasonCategory,
+        ReasonForNoCurrent,
+    };
+    use crate::api::{CommandReply, UtcDateTime};
+
+    #[test]
+    fn historical_samples() {
+        let sample = |value| {
+            serde_json::from_value::<ObservationSample>(json!({
+                "timestamp": "2024-05-01T10:00:00Z",
+                "value": value,
+            }))
+            .unwrap()
+        };
+        assert!(matches!(
+            sample(json!(0)).observation(120),
+            Observation::TotalPower(p) if p == 0.0
+        ));
+        assert!(matches!(
+            sample(json!(7.2)).observation(120),
+            Observation::TotalPower(p) if p == 7.2
+        ));
+        assert!(matches!(
+            sample(json!(3)).observation(109),
+            Observation::ChargerOpMode(ChargerOpMode::Charging)
+        ));
+        assert!(matches!(
+            sample(json!(true)).observation(103),
+            Observation::CableLocked(true)
+        ));
+        assert!(matches!(
+            sample(json!(5)).observation(999),
+            Observation::Unknown {
+                code: 999,
+                value: ObservationData::Integer(5)
+            }
+        ));
+    }
+
+    #[test]
+    fn envelope_format() {
+        let event = Event {
+            charger: "EH000001".parse().unwrap(),
+            observation: Observation::TotalPower(3.5),
+        };
+        let at = UtcDateTime("2024-05-01T12:00:00Z".parse().unwrap());
+        assert_eq!(
+            serde_json::to_value(event.envelope(EventSource::Stream, at)).unwrap(),
+            json!({
+                "schema": 1,
+                "emitted_at": "2024-05-01T12:00:00Z",
+                "source": "stream",
+                "charger": "EH000001",
+                "observation": {"type": "TotalPower", "value": 3.5},
+            })
+        );
+    }
+
+    #[test]
+    fn command_response_matches_reply() {
+        let resp = CommandResponse::deserialize(&json!({
+            "serialNumber": "EH000001",
+            "id": 48,
+            "timestamp": "2024-05-01T12:00:01Z",
+            "deliveredAt": "2024-05-01T12:00:01Z",
+            "wasAccepted": true,
+            "resultCode": 0,
+            "comment": null,
+            "ticks": 638501184000000000u64,
+        }))
+        .unwrap();
+        let mut reply = CommandReply {
+            command_id: 48,
+            device: "EH000001".to_owned(),
+            ticks: 638501184000000000,
+        };
+        assert!(resp.was_accepted);
+        assert!(resp.matches(&reply));
+        reply.ticks += 1;
+        assert!(!resp.matches(&reply));
+    }
+
+    #[test]
+    fn reason_for_no_current() {
+        let reason: ReasonForNoCurrent = serde_json::from_value(json!(76)).unwrap();
+        assert_eq!(reason, ReasonForNoCurrent::Limit(LimitReason::Schedule));
+        assert_eq!(reason.category(), ReasonCategory::Limit);
+        assert_eq!(reason.to_string(), "Schedule: limited by schedule");
+        assert_eq!(serde_json::to_value(reason).unwrap(), json!(76));
+        assert_eq!(ReasonForNoCurrent::from(42), ReasonForNoCurrent::Unknown(42));
+        assert_eq!(ReasonForNoCurrent::from(42).to_string(), "Code 42");
+    }
+
+    #[test]
+    fn pascal_case_keys() {
+        let camel = json!({"dataType": 3, "id": 120, "mid": "EH000001", "value": "7.2"});
+        let pascal = json!({"DataType": 3, "Id": 120, "Mid": "EH000001", "Value": "7.2"});
+        for update in [camel, pascal] {
+            let event = decode_update(ProductUpdate::deserialize(&update).unwrap()).unwrap();
+            assert_eq!(event.charger, "EH000001");
+            assert!(matches!(event.observation, Observation::TotalPower(p) if p == 7.2));
+        }
+
+        let resp = CommandResponse::deserialize(&json!({
+            "SerialNumber": "EH000001",
+            "Id": 48,
+            "Ticks": 638501184000000000u64,
+            "WasAccepted": false,
+            "ResultCode": 1,
+        }))
+        .unwrap();
+        assert!(!resp.was_accepted);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::debug;
+
+/// A token bucket stored in a file, shared by all the processes using the
+/// same path. Each request takes one token; tokens are replenished at a fixed
+/// rate, up to a maximum burst.
+///
+/// Access to the file is serialized with an exclusive file lock, so this
+/// works across unrelated processes on the same host.
+#[derive(Clone, Debug)]
+pub struct SharedRateBudget {
+    path: PathBuf,
+    per_second: f64,
+    burst: f64,
+}
+
+struct BucketState {
+    tokens: f64,
+    updated_ms: u64,
+}
+
+/// Replenish `tokens` for the elapsed time, then take one, or return how
+/// long to wait for the next one
+fn take(tokens: &mut f64, elapsed: f64, per_second: f64, burst: f64) -> Option<Duration> {
+    *tokens = (*tokens + elapsed * per_second).min(burst);
+    if *tokens >= 1.0 {
+        *tokens -= 1.0;
+        None
+    } else {
+        Some(Duration::from_secs_f64((1.0 - *tokens) / per_second))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl SharedRateBudget {
+    /// Allow `per_minute` requests per minute on average, and up to `burst` at
+    /// once. A rate of zero is raised to one request per minute.
+    pub fn new<P: AsRef<Path>>(path: P, per_minute: u32, burst: u32) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            per_second: per_minute.max(1) as f64 / 60.0,
+            burst: burst.max(1) as f64,
+        }
+    }
+
+    fn read(&self, file: &mut File, now: u64) -> io::Result<BucketState> {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut fields = content.split_whitespace().map(str::parse::<f64>);
+        let state = match (fields.next(), fields.next()) {
+            (Some(Ok(tokens)), Some(Ok(updated))) => BucketState {
+                tokens,
+                updated_ms: updated as u64,
+            },
+            _ => BucketState {
+                tokens: self.burst,
+                updated_ms: now,
+            },
+        };
+        Ok(state)
+    }
+
+    fn write(file: &mut File, state: &BucketState) -> io::Result<()> {
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        write!(file, "{} {}", state.tokens, state.updated_ms)
+    }
+
+    /// Take a token from the bucket, or return how long to wait for the next one
+    fn try_acquire(&self) -> io::Result<Option<Duration>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        file.lock()?;
+
+        let now = now_ms();
+        let mut state = self.read(&mut file, now)?;
+
+        // A clock going backwards must not mint tokens
+        let elapsed = now.saturating_sub(state.updated_ms) as f64 / 1000.0;
+        state.updated_ms = now;
+        let wait = take(&mut state.tokens, elapsed, self.per_second, self.burst);
+
+        Self::write(&mut file, &state)?;
+        file.unlock()?;
+        Ok(wait)
+    }
+
+    /// Block until a request may be sent
+    pub fn acquire(&self) -> io::Result<()> {
+        while let Some(wait) = self.try_acquire()? {
+            debug!("Rate budget exhausted, waiting {wait:?}");
+            thread::sleep(wait);
+        }
+        Ok(())
+    }
+}
+
+/// A token bucket local to the process. Clones share the same bucket, so
+/// that several contexts of the same account can use a common limit.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    state: Arc<Mutex<(f64, Instant)>>,
+    per_second: f64,
+    burst: f64,
+}
+
+impl RateLimiter {
+    /// Allow `per_minute` requests per minute on average, and up to `burst` at
+    /// once. A rate of zero is raised to one request per minute.
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            state: Arc::new(Mutex::new((burst, Instant::now()))),
+            per_second: per_minute.max(1) as f64 / 60.0,
+            burst,
+        }
+    }
+
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, updated) = &mut *state;
+        let now = Instant::now();
+        let elapsed = (now - *updated).as_secs_f64();
+        *updated = now;
+        take(tokens, elapsed, self.per_second, self.burst)
+    }
+
+    /// Block until a request may be sent
+    pub fn acquire(&self) {
+        while let Some(wait) = self.try_acquire() {
+            debug!("Rate limit reached, waiting {wait:?}");
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RateLimiter, SharedRateBudget};
+
+    #[test]
+    fn shared_bucket() {
+        let path =
+            std::env::temp_dir().join(format!("easee-rs-test-{}.budget", std::process::id()));
+        let a = SharedRateBudget::new(&path, 60, 2);
+        let b = SharedRateBudget::new(&path, 60, 2);
+
+        assert!(a.try_acquire().unwrap().is_none());
+        assert!(b.try_acquire().unwrap().is_none());
+        assert!(a.try_acquire().unwrap().is_some());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn shared_bucket_zero_rate() {
+        let path = std::env::temp_dir().join(format!("easee-rs-test-{}.zero", std::process::id()));
+        let budget = SharedRateBudget::new(&path, 0, 1);
+        assert!(budget.try_acquire().unwrap().is_none());
+        let wait = budget.try_acquire().unwrap().unwrap();
+        assert!(wait <= std::time::Duration::from_secs(60));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn local_bucket() {
+        let a = RateLimiter::new(60, 2);
+        let b = a.clone();
+        assert!(a.try_acquire().is_none());
+        assert!(b.try_acquire().is_none());
+        let wait = a.try_acquire().unwrap();
+        assert!(wait <= std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn local_bucket_zero_rate() {
+        let limiter = RateLimiter::new(0, 0);
+        assert!(limiter.try_acquire().is_none());
+        let wait = limiter.try_acquire().unwrap();
+        assert!(wait <= std::time::Duration::from_secs(60));
+    }
+}
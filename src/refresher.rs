@@ -0,0 +1,137 @@
+use std::{
+    io,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use tracing::{debug, warn};
+
+use crate::{api::Context, shutdown::Shutdown};
+
+/// Delay before trying again after a failed refresh
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest wait between two checks of the shutdown signal
+const SHUTDOWN_POLL: Duration = Duration::from_secs(1);
+
+/// Renews the tokens of a context from a background thread, `margin` before
+/// they expire, so that calls never have to. Stops when dropped, or when
+/// `shutdown` is triggered.
+pub struct Refresher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Refresher {
+    pub fn spawn(
+        ctx: Arc<Mutex<Context>>,
+        margin: Duration,
+        shutdown: &Shutdown,
+    ) -> io::Result<Self> {
+        let (stop, stopped) = mpsc::channel();
+        let shutdown = shutdown.clone();
+        let thread = thread::Builder::new()
+            .name("easee-refresh".to_owned())
+            .spawn(move || run(&ctx, margin, &stopped, &shutdown))?;
+        Ok(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Wait for `duration`, checking the shutdown signal at least every
+/// [`SHUTDOWN_POLL`]. Returns false if the refresher must stop.
+fn wait(stopped: &mpsc::Receiver<()>, shutdown: &Shutdown, duration: Duration) -> bool {
+    let mut left = duration;
+    loop {
+        if shutdown.is_triggered() {
+            return false;
+        }
+        let slice = left.min(SHUTDOWN_POLL);
+        match stopped.recv_timeout(slice) {
+            Err(RecvTimeoutError::Timeout) => (),
+            _ => return false,
+        }
+        left -= slice;
+        if left.is_zero() {
+            return !shutdown.is_triggered();
+        }
+    }
+}
+
+fn run(ctx: &Mutex<Context>, margin: Duration, stopped: &mpsc::Receiver<()>, shutdown: &Shutdown) {
+    let lock = || ctx.lock().unwrap_or_else(|e| e.into_inner());
+    let mut wait_for = lock().token_expires_in().saturating_sub(margin);
+    loop {
+        if !wait(stopped, shutdown, wait_for) {
+            debug!("Stopping token refresher");
+            return;
+        }
+
+        let mut ctx = lock();
+        if ctx.token_expires_in() > margin {
+            // Refreshed in the meantime by a call
+            wait_for = ctx.token_expires_in() - margin;
+            continue;
+        }
+        debug!("Refreshing token ahead of expiration");
+        wait_for = match ctx.refresh_token() {
+            // A margin longer than the token lifetime must not spin
+            Ok(()) => ctx
+                .token_expires_in()
+                .saturating_sub(margin)
+                .max(RETRY_DELAY),
+            Err(e) => {
+                warn!("Background token refresh failed: {e}");
+                RETRY_DELAY
+            }
+        };
+    }
+}
+
+impl Drop for Refresher {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use super::Refresher;
+    use crate::{api::Context, shutdown::Shutdown};
+
+    #[test]
+    fn stops_on_shutdown() {
+        let ctx = Context::new(
+            "aaaaaaa0",
+            "abcdef".into(),
+            Instant::now() + Duration::from_secs(3600),
+        );
+        let shutdown = Shutdown::new();
+        let mut refresher = Refresher::spawn(
+            Arc::new(Mutex::new(ctx)),
+            Duration::from_secs(60),
+            &shutdown,
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        shutdown.trigger();
+        // Join the thread without closing the stop channel first
+        refresher.thread.take().unwrap().join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
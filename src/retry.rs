@@ -0,0 +1,103 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::api::ApiError;
+
+/// How failed idempotent requests are retried: up to `max_attempts` in
+/// total, waiting `base_delay` doubled at every attempt, capped at `max_delay`.
+/// A random part of up to `jitter` times the delay is added, so that clients
+/// failing together don't retry together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.max(0.0);
+        self
+    }
+
+    /// Whether an error is worth retrying: rate limiting, server errors,
+    /// and failures to reach the server
+    pub fn is_transient(error: &ApiError) -> bool {
+        match error {
+            ApiError::Server { status, .. } => *status == 429 || *status >= 500,
+            ApiError::Ureq(e) => matches!(**e, ureq::Error::Transport(_)),
+            ApiError::IO(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Delay before the given retry, starting at 1, without jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Delay before the given retry, starting at 1
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff(retry)
+            .mul_f64(1.0 + self.jitter * random_fraction())
+    }
+}
+
+/// A number in [0, 1), good enough to spread retries
+fn random_fraction() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    // Scramble the low bits, which change the fastest
+    let mixed = nanos.wrapping_mul(2654435761) >> 8;
+    mixed as f64 / (1u32 << 24) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::default()
+            .base_delay(Duration::from_millis(500))
+            .max_delay(Duration::from_secs(3))
+            .jitter(0.5);
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(3));
+
+        let delay = policy.delay(2);
+        assert!(delay >= Duration::from_secs(1) && delay < Duration::from_millis(1500));
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+/// Report about an API response that didn't match what this crate expects.
+/// This usually means Easee changed the API.
+#[derive(Clone, Debug)]
+pub enum SchemaEvent {
+    /// The response contained fields this crate doesn't know about
+    UnknownFields {
+        endpoint: String,
+        version: u32,
+        fields: Vec<String>,
+    },
+
+    /// The response could not be decoded at all
+    UnexpectedShape { endpoint: String, error: String },
+}
+
+type SchemaCallback = Box<dyn FnMut(&SchemaEvent) + Send>;
+
+#[derive(Default)]
+struct EndpointSchema {
+    version: u32,
+    unknown: BTreeSet<String>,
+}
+
+/// Keeps track of the response shapes observed for each endpoint. The
+/// version of an endpoint starts at 1, and is bumped every time new unknown
+/// fields show up.
+#[derive(Default)]
+pub(crate) struct SchemaTracker {
+    endpoints: HashMap<String, EndpointSchema>,
+    callback: Option<SchemaCallback>,
+}
+
+/// Replace the ID-like segments of an API path, so that all calls to the same
+/// endpoint share the same key. IDs and dates are the only segments with digits.
+/// The query string is dropped.
+pub(crate) fn endpoint_template(path: &str) -> String {
+    let path = path.split_once("/api/").map_or(path, |(_, p)| p);
+    let path = path.split_once('?').map_or(path, |(p, _)| p);
+    path.split('/')
+        .map(|seg| {
+            if seg.chars().any(|c| c.is_ascii_digit()) {
+                "{}"
+            } else {
+                seg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn field_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, .. } => format!("{}[]", field_path(parent)),
+        Path::Map { parent, key } => match field_path(parent) {
+            p if p.is_empty() => key.clone(),
+            p => format!("{p}.{key}"),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => field_path(parent),
+    }
+}
+
+impl SchemaTracker {
+    pub(crate) fn set_callback(&mut self, callback: SchemaCallback) {
+        self.callback = Some(callback);
+    }
+
+    fn emit(&mut self, event: SchemaEvent) {
+        warn!("API schema mismatch: {event:?}");
+        if let Some(cb) = &mut self.callback {
+            cb(&event);
+        }
+    }
+
+    pub(crate) fn version(&self, endpoint: &str) -> Option<u32> {
+        self.endpoints.get(endpoint).map(|e| e.version)
+    }
+
+    /// Decode a response, recording any field that was ignored
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        value: &serde_json::Value,
+    ) -> Result<T, serde_json::Error> {
+        let endpoint = endpoint_template(path);
+        let mut ignored = BTreeSet::new();
+        let parsed = serde_ignored::deserialize(value, |p| {
+            ignored.insert(field_path(&p));
+        });
+
+        if let Err(e) = &parsed {
+            self.emit(SchemaEvent::UnexpectedShape {
+                endpoint,
+                error: e.to_string(),
+            });
+            return parsed;
+        }
+
+        let schema = self.endpoints.entry(endpoint.clone()).or_default();
+        let new: Vec<String> = ignored.difference(&schema.unknown).cloned().collect();
+        if schema.version == 0 || !new.is_empty() {
+            schema.version += 1;
+        }
+        if !new.is_empty() {
+            schema.unknown.extend(new.iter().cloned());
+            let version = schema.version;
+            self.emit(SchemaEvent::UnknownFields {
+                endpoint,
+                version,
+                fields: new,
+            });
+        }
+
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::{endpoint_template, SchemaTracker};
+
+    #[test]
+    fn unknown_fields_bump_version() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Known {
+            a: u32,
+        }
+
+        assert_eq!(
+            endpoint_template("https://api.easee.com/api/chargers/EH12345/state"),
+            "chargers/{}/state"
+        );
+
+        let mut tracker = SchemaTracker::default();
+        let path = "chargers/EH1/state";
+        let _: Known = tracker.deserialize(path, &json!({"a": 1})).unwrap();
+        assert_eq!(tracker.version("chargers/{}/state"), Some(1));
+
+        let _: Vec<Known> = tracker
+            .deserialize("chargers/EH2/state", &json!([{"a": 1, "b": 2}]))
+            .unwrap();
+        let _: Vec<Known> = tracker
+            .deserialize(path, &json!([{"a": 1, "b": 3}]))
+            .unwrap();
+        assert_eq!(tracker.version("chargers/{}/state"), Some(2));
+        assert!(tracker.endpoints["chargers/{}/state"]
+            .unknown
+            .contains("[].b"));
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer};
+
+#[cfg(not(feature = "secrecy"))]
+type Inner = String;
+
+/// With the `secrecy` feature, the memory is wiped when the secret is dropped
+#[cfg(feature = "secrecy")]
+type Inner = secrecy::SecretString;
+
+/// A string holding a secret (token, password...), which never shows up in
+/// `Debug` or `Display` output. Use [`SecretString::expose`] to access it.
+#[derive(Clone)]
+pub struct SecretString(Inner);
+
+impl SecretString {
+    // The conversion is only useless without the `secrecy` feature
+    #[allow(clippy::useless_conversion)]
+    pub fn new(secret: String) -> Self {
+        SecretString(secret.into())
+    }
+
+    /// Access the secret value
+    pub fn expose(&self) -> &str {
+        #[cfg(feature = "secrecy")]
+        return secrecy::ExposeSecret::expose_secret(&self.0);
+        #[cfg(not(feature = "secrecy"))]
+        return &self.0;
+    }
+}
+
+impl Default for SecretString {
+    fn default() -> Self {
+        Self::new(String::new())
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.expose() == other.expose()
+    }
+}
+
+impl Eq for SecretString {}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d).map(Self::new)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self::new(value.to_owned())
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<secrecy::SecretString> for SecretString {
+    fn from(value: secrecy::SecretString) -> Self {
+        SecretString(value)
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<SecretString> for secrecy::SecretString {
+    fn from(value: SecretString) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<secret>")
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<secret>")
+    }
+}
+
+/// Replace the values of all token- or password-like keys in a JSON document
+pub(crate) fn redact(mut value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match &mut value {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                let key = k.to_lowercase();
+                if key.contains("token") || key.contains("password") {
+                    *v = Value::String("<secret>".to_owned());
+                } else {
+                    *v = redact(v.take());
+                }
+            }
+        }
+        Value::Array(items) => {
+            for v in items.iter_mut() {
+                *v = redact(v.take());
+            }
+        }
+        _ => (),
+    }
+    value
+}
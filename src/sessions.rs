@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
+use tracing::{info, warn};
+
+use crate::api::{ApiError, Charger, ChargerOpMode, ChargingSession, Context, UtcDateTime};
+use crate::ids::ChargerId;
+
+/// Outcome of [`GapDetector::poll`] for each charger queried. Chargers whose
+/// query failed are queried again on the next poll.
+pub type GapResults = Vec<(ChargerId, Result<GapEvent, ApiError>)>;
+
+/// Outcome of the verification of an observed charging period
+#[derive(Clone, Debug)]
+pub enum GapEvent {
+    /// A session record appeared for the observed charging
+    Healed {
+        charger: ChargerId,
+        session: ChargingSession,
+    },
+
+    /// Charging was observed, but no session record appeared
+    MissingSession {
+        charger: ChargerId,
+        started: UtcDateTime,
+        ended: UtcDateTime,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Pending {
+    started: UtcDateTime,
+    ended: UtcDateTime,
+    deadline: UtcDateTime,
+    attempts: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Track {
+    charging_since: Option<UtcDateTime>,
+    last_session: Option<i32>,
+    pending: Option<Pending>,
+}
+
+/// Checks that every charging period observed on the stream eventually shows
+/// up as a session record in the REST API.
+///
+/// Once charging stops, the latest session of the charger is queried after
+/// `grace`, and again up to `attempts` times. If a new session appears it is
+/// reported as [`GapEvent::Healed`], otherwise as [`GapEvent::MissingSession`].
+#[derive(Clone, Debug)]
+pub struct GapDetector {
+    chargers: HashMap<ChargerId, Charger>,
+    tracks: HashMap<ChargerId, Track>,
+    pub grace: Duration,
+    pub attempts: u32,
+}
+
+impl GapDetector {
+    /// Start tracking the given chargers, recording their latest known session
+    pub fn new(ctx: &mut Context, chargers: Vec<Charger>) -> Result<Self, ApiError> {
+        let mut tracks = HashMap::new();
+        for charger in &chargers {
+            let last_session = charger.latest_session(ctx)?.and_then(|s| s.session_id);
+            tracks.insert(
+                charger.id.clone(),
+                Track {
+                    last_session,
+                    ..Default::default()
+                },
+            );
+        }
+
+        Ok(Self {
+            chargers: chargers.into_iter().map(|c| (c.id.clone(), c)).collect(),
+            tracks,
+            grace: Duration::minutes(5),
+            attempts: 3,
+        })
+    }
+
+    /// Charging started or resumed. A charging period still waiting for its
+    /// session record is abandoned, as the session turned out to go on.
+    pub fn charging_started(&mut self, charger: &str, now: UtcDateTime) {
+        if let Some(track) = self.tracks.get_mut(charger) {
+            track.charging_since.get_or_insert(now);
+            track.pending = None;
+        }
+    }
+
+    pub fn charging_stopped(&mut self, charger: &str, now: UtcDateTime) {
+        let grace = self.grace;
+        let Some(track) = self.tracks.get_mut(charger) else {
+            return;
+        };
+        let Some(started) = track.charging_since.take() else {
+            return;
+        };
+        track.pending = Some(Pending {
+            started,
+            ended: now,
+            deadline: UtcDateTime(now.0 + grace),
+            attempts: 0,
+        });
+    }
+
+    /// Track a change of operating mode. Only a finished session or a
+    /// disconnected car end the charging period; a paused session may resume.
+    pub fn op_mode_changed(&mut self, charger: &str, mode: ChargerOpMode, now: UtcDateTime) {
+        match mode {
+            ChargerOpMode::Charging => self.charging_started(charger, now),
+            ChargerOpMode::Finished | ChargerOpMode::Disconnected => {
+                self.charging_stopped(charger, now)
+            }
+            _ => {}
+        }
+    }
+
+    /// Feed an observation event, streamed or read from the REST API
+    pub fn observe(&mut self, event: &crate::observation::Event, now: UtcDateTime) {
+        if let crate::observation::Observation::ChargerOpMode(mode) = event.observation {
+            self.op_mode_changed(&event.charger, mode, now);
+        }
+    }
+
+    /// Query the API for the charging periods whose grace period is over.
+    /// Chargers with nothing to report are left out.
+    pub fn poll(&mut self, ctx: &mut Context, now: UtcDateTime) -> GapResults {
+        let chargers = std::mem::take(&mut self.chargers);
+        let results = self.poll_with(now, |id| chargers[id].latest_session(ctx));
+        self.chargers = chargers;
+        results
+    }
+
+    fn poll_with<F>(&mut self, now: UtcDateTime, mut latest_session: F) -> GapResults
+    where
+        F: FnMut(&ChargerId) -> Result<Option<ChargingSession>, ApiError>,
+    {
+        let mut results = vec![];
+        for (id, track) in &mut self.tracks {
+            let Some(pending) = &mut track.pending else {
+                continue;
+            };
+            if pending.deadline > now {
+                continue;
+            }
+
+            let latest = match latest_session(id) {
+                Ok(latest) => latest,
+                Err(e) => {
+                    warn!("Charger {id}: could not read the latest session: {e}");
+                    results.push((id.clone(), Err(e)));
+                    continue;
+                }
+            };
+            let event = match latest {
+                Some(session) if session.session_id != track.last_session => {
+                    info!("Charger {id}: session record found");
+                    track.last_session = session.session_id;
+                    track.pending = None;
+                    GapEvent::Healed {
+                        charger: id.clone(),
+                        session,
+                    }
+                }
+                _ if pending.attempts + 1 >= self.attempts => {
+                    warn!(
+                        "Charger {id}: no session record for charging observed until {:?}",
+                        pending.ended
+                    );
+                    let event = GapEvent::MissingSession {
+                        charger: id.clone(),
+                        started: pending.started,
+                        ended: pending.ended,
+                    };
+                    track.pending = None;
+                    event
+                }
+                _ => {
+                    pending.attempts += 1;
+                    pending.deadline = UtcDateTime(now.0 + self.grace);
+                    continue;
+                }
+            };
+            results.push((id.clone(), Ok(event)));
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use chrono::Duration;
+    use serde_json::json;
+
+    use super::{GapDetector, GapEvent, Track};
+    use crate::api::{ApiError, ChargerOpMode, ChargingSession, UtcDateTime};
+    use crate::ids::ChargerId;
+
+    fn detector(chargers: &[&str]) -> GapDetector {
+        let tracks = chargers
+            .iter()
+            .map(|id| {
+                let track = Track {
+                    last_session: Some(1),
+                    ..Default::default()
+                };
+                (id.parse().unwrap(), track)
+            })
+            .collect();
+        GapDetector {
+            chargers: HashMap::new(),
+            tracks,
+            grace: Duration::minutes(5),
+            attempts: 3,
+        }
+    }
+
+    fn session(id: i32) -> Option<ChargingSession> {
+        serde_json::from_value(json!({"sessionEnergy": 1.0, "sessionId": id})).ok()
+    }
+
+    fn at(minutes: i64) -> UtcDateTime {
+        let start: chrono::DateTime<chrono::Utc> = "2024-05-01T10:00:00Z".parse().unwrap();
+        UtcDateTime(start + Duration::minutes(minutes))
+    }
+
+    fn charge(detector: &mut GapDetector, charger: &str, from: i64, to: i64) {
+        detector.op_mode_changed(charger, ChargerOpMode::Charging, at(from));
+        detector.op_mode_changed(charger, ChargerOpMode::Finished, at(to));
+    }
+
+    #[test]
+    fn paused_session_is_not_reported() {
+        let mut detector = detector(&["EH1"]);
+        detector.op_mode_changed("EH1", ChargerOpMode::Charging, at(0));
+        detector.op_mode_changed("EH1", ChargerOpMode::Paused, at(10));
+        assert!(detector.poll_with(at(30), |_| Ok(session(1))).is_empty());
+
+        // A pending period is abandoned when charging resumes
+        charge(&mut detector, "EH1", 40, 50);
+        detector.op_mode_changed("EH1", ChargerOpMode::Charging, at(52));
+        assert!(detector.poll_with(at(60), |_| Ok(session(1))).is_empty());
+    }
+
+    #[test]
+    fn healed_and_missing() {
+        let mut detector = detector(&["EH1", "EH2"]);
+        charge(&mut detector, "EH1", 0, 10);
+        charge(&mut detector, "EH2", 0, 10);
+        let latest = |id: &ChargerId| Ok(if id == "EH1" { session(2) } else { session(1) });
+
+        let results = detector.poll_with(at(15), latest);
+        assert_eq!(results.len(), 1);
+        assert!(
+            matches!(&results[0].1, Ok(GapEvent::Healed { session, .. }) if session.session_id == Some(2))
+        );
+
+        assert!(detector.poll_with(at(20), latest).is_empty());
+        let results = detector.poll_with(at(25), latest);
+        assert_eq!(results[0].0, "EH2");
+        assert!(
+            matches!(&results[0].1, Ok(GapEvent::MissingSession { ended, .. }) if *ended == at(10))
+        );
+        assert!(detector.poll_with(at(60), latest).is_empty());
+    }
+
+    #[test]
+    fn error_midway_keeps_other_events() {
+        let mut detector = detector(&["EH1", "EH2"]);
+        charge(&mut detector, "EH1", 0, 10);
+        charge(&mut detector, "EH2", 0, 10);
+        let failing = |id: &ChargerId| {
+            if id == "EH1" {
+                Err(ApiError::InvalidID(id.to_string()))
+            } else {
+                Ok(session(2))
+            }
+        };
+
+        let mut results = detector.poll_with(at(15), failing);
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        assert!(results[0].1.is_err());
+        assert!(matches!(results[1].1, Ok(GapEvent::Healed { .. })));
+
+        // The failed charger is queried again
+        let results = detector.poll_with(at(16), |_| Ok(session(3)));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "EH1");
+        assert!(results[0].1.is_ok());
+    }
+}
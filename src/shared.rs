@@ -0,0 +1,58 @@
+use std::{
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::api::{ApiError, Context};
+use crate::refresher::Refresher;
+use crate::shutdown::Shutdown;
+
+/// A [`Context`] usable from many threads at once. Clones share the same
+/// tokens.
+///
+/// Calls made through [`SharedContext::context`] run concurrently, each on
+/// its own lightweight context; only the token refresh is serialized. Those
+/// contexts refresh their tokens through the shared context, so that the
+/// tokens are rotated once for all of them. Run a
+/// [`SharedContext::spawn_refresher`] to renew them ahead of expiration.
+#[derive(Clone, Debug)]
+pub struct SharedContext {
+    inner: Arc<Mutex<Context>>,
+}
+
+impl SharedContext {
+    pub fn new(ctx: Context) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ctx)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Context> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A context for the calls of the current task, carrying fresh tokens
+    pub fn context(&self) -> Result<Context, ApiError> {
+        self.lock().fork_shared(Some(self.inner.clone()))
+    }
+
+    /// Run `f` on the shared context itself, holding the lock for its whole
+    /// duration. Needed for calls using the state of the context, such as the
+    /// cached topology or the paused circuits.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Context) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Refresh the tokens `margin` before they expire, until the returned
+    /// handle is dropped or `shutdown` is triggered
+    pub fn spawn_refresher(&self, margin: Duration, shutdown: &Shutdown) -> io::Result<Refresher> {
+        Refresher::spawn(self.inner.clone(), margin, shutdown)
+    }
+}
+
+impl From<Context> for SharedContext {
+    fn from(ctx: Context) -> Self {
+        Self::new(ctx)
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    io,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use tracing::{info, warn};
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct State {
+    triggered: Mutex<bool>,
+    cond: Condvar,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    hooks: Mutex<Vec<Hook>>,
+}
+
+/// Shutdown signal shared by all the background components of an application.
+///
+/// Workers started with [`Shutdown::spawn`] are expected to poll
+/// [`Shutdown::is_triggered`] or sleep with [`Shutdown::sleep`] between
+/// iterations. When the application receives SIGTERM (use your signal
+/// handling crate of choice), call [`Shutdown::trigger`] then
+/// [`Shutdown::finish`] to wait for the workers and run the cleanup hooks.
+///
+/// The event stream thread started by `bootstrap` is such a worker, and a
+/// [`crate::refresher::Refresher`] stops on its own once shutdown is triggered.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    state: Arc<State>,
+}
+
+impl std::fmt::Debug for Shutdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shutdown")
+            .field("triggered", &self.is_triggered())
+            .finish()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask all components to stop
+    pub fn trigger(&self) {
+        let mut triggered = self.state.triggered.lock().unwrap();
+        if !*triggered {
+            info!("Shutdown requested");
+            *triggered = true;
+            self.state.cond.notify_all();
+        }
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.state.triggered.lock().unwrap()
+    }
+
+    /// Sleep for the given duration, waking up early on shutdown.
+    /// Returns true if shutdown was triggered.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let triggered = self.state.triggered.lock().unwrap();
+        let (triggered, _) = self
+            .state
+            .cond
+            .wait_timeout_while(triggered, duration, |t| !*t)
+            .unwrap();
+        *triggered
+    }
+
+    /// Block until shutdown is triggered
+    pub fn wait(&self) {
+        let triggered = self.state.triggered.lock().unwrap();
+        let _unused = self.state.cond.wait_while(triggered, |t| !*t).unwrap();
+    }
+
+    /// Run `f` in a named thread, which will be joined by [`Shutdown::finish`]
+    pub fn spawn<F: FnOnce(Shutdown) + Send + 'static>(&self, name: &str, f: F) -> io::Result<()> {
+        let handle = self.clone();
+        let worker = thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || f(handle))?;
+        self.state.workers.lock().unwrap().push(worker);
+        Ok(())
+    }
+
+    /// Register a cleanup action, such as flushing a sink or closing a
+    /// connection. Hooks run in registration order, after all workers stopped.
+    pub fn on_shutdown<F: FnOnce() + Send + 'static>(&self, hook: F) {
+        self.state.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// Trigger shutdown if it wasn't already, wait for all workers, then run the cleanup hooks
+    pub fn finish(&self) {
+        self.trigger();
+
+        let workers = std::mem::take(&mut *self.state.workers.lock().unwrap());
+        for worker in workers {
+            let name = worker.thread().name().unwrap_or("<unnamed>").to_owned();
+            if worker.join().is_err() {
+                warn!("Worker {name} panicked");
+            }
+        }
+
+        let hooks = std::mem::take(&mut *self.state.hooks.lock().unwrap());
+        for hook in hooks {
+            hook();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::Shutdown;
+
+    #[test]
+    fn workers_stop_before_hooks() {
+        let shutdown = Shutdown::new();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let c = counter.clone();
+        shutdown
+            .spawn("worker", move |s| {
+                while !s.sleep(Duration::from_millis(10)) {}
+                c.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        let c = counter.clone();
+        shutdown.on_shutdown(move || assert_eq!(c.fetch_add(1, Ordering::SeqCst), 1));
+
+        shutdown.finish();
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+}
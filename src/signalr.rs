@@ -1,6 +1,14 @@
 use serde_json::{json, Value};
 use thiserror::Error;
 
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use tungstenite::stream::MaybeTlsStream;
+
 use crate::stream::RecvError;
 
 /* This entire module can be rewritten in two lines when
@@ -103,13 +111,37 @@ pub enum StreamError {
     StreamError(#[from] RecvError),
 }
 
-pub struct Stream {
+impl StreamError {
+    /// Stable identifier of the kind of error, see [`crate::api::ApiError::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            StreamError::ParseError(_) => "stream.parse",
+            StreamError::StreamError(RecvError::BadMessageType) => "stream.message_type",
+            StreamError::StreamError(RecvError::InvalidJson(_)) => "stream.json",
+            StreamError::StreamError(RecvError::TungsteniteError(_)) => "stream.websocket",
+        }
+    }
+
+    /// See [`RecvError::is_timeout`]
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, StreamError::StreamError(e) if e.is_timeout())
+    }
+}
+
+pub struct Stream<S = MaybeTlsStream<TcpStream>> {
     buffer: Vec<serde_json::Value>,
-    ws: super::stream::Stream,
+    ws: super::stream::Stream<S>,
 }
 
 impl Stream {
-    pub fn from_ws(ws: super::stream::Stream) -> Self {
+    /// See [`crate::stream::Stream::set_read_timeout`]
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.ws.set_read_timeout(timeout)
+    }
+}
+
+impl<S: Read + Write> Stream<S> {
+    pub fn from_ws(ws: super::stream::Stream<S>) -> Self {
         Self { ws, buffer: vec![] }
     }
 
@@ -123,6 +155,10 @@ impl Stream {
         Ok(Message::from_json(json)?)
     }
 
+    pub fn close(&mut self) -> Result<(), StreamError> {
+        Ok(self.ws.close()?)
+    }
+
     pub fn invoke(
         &mut self,
         target: &str,
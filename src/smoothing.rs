@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+/// A filter over a series of measurements
+pub trait Filter {
+    /// Add a measurement, and return the filtered value
+    fn push(&mut self, value: f64) -> f64;
+
+    /// Last filtered value, if any measurement was pushed
+    fn value(&self) -> Option<f64>;
+}
+
+/// Exponential moving average
+#[derive(Clone, Copy, Debug)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    /// `alpha` is the weight of a new measurement, between 0 and 1
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            value: None,
+        }
+    }
+}
+
+impl Filter for Ema {
+    fn push(&mut self, value: f64) -> f64 {
+        let v = match self.value {
+            Some(prev) => prev + self.alpha * (value - prev),
+            None => value,
+        };
+        self.value = Some(v);
+        v
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Median of the last N measurements. Good at rejecting isolated spikes.
+#[derive(Clone, Debug)]
+pub struct Median {
+    size: usize,
+    window: VecDeque<f64>,
+}
+
+impl Median {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            window: VecDeque::new(),
+        }
+    }
+}
+
+impl Filter for Median {
+    fn push(&mut self, value: f64) -> f64 {
+        if self.window.len() == self.size {
+            self.window.pop_front();
+        }
+        self.window.push_back(value);
+        self.value().unwrap()
+    }
+
+    fn value(&self) -> Option<f64> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.window.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        Some(if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        })
+    }
+}
+
+#[cfg(feature = "tungstenite")]
+pub use self::events::EventSmoother;
+
+#[cfg(feature = "tungstenite")]
+mod events {
+    use std::{collections::HashMap, mem::Discriminant};
+
+    use super::Filter;
+    use crate::ids::ChargerId;
+    use crate::observation::{Event, InputPin, Observation};
+
+    type Key = (ChargerId, Discriminant<Observation>, u8);
+
+    /// Smooths the power and current values of stream events, keeping one
+    /// filter per charger and per measurement.
+    #[derive(Clone, Debug)]
+    pub struct EventSmoother<F> {
+        template: F,
+        filters: HashMap<Key, F>,
+    }
+
+    fn pin_index(pin: InputPin) -> u8 {
+        match pin {
+            InputPin::T1 => 1,
+            InputPin::T2 => 2,
+            InputPin::T3 => 3,
+            InputPin::T4 => 4,
+            InputPin::T5 => 5,
+        }
+    }
+
+    /// The measured value carried by an observation, if it is worth smoothing
+    fn measurement(obs: &mut Observation) -> Option<(u8, &mut f64)> {
+        use Observation::*;
+        match obs {
+            TotalPower(v) | EnergyPerHour(v) | DynamicChargerCurrent(v) => Some((0, v)),
+            CircuitTotalCurrent { phase, amperes } => Some((*phase, amperes)),
+            IntCurrent { pin, current } => Some((pin_index(*pin), current)),
+            IntVoltage { pins, voltage } => {
+                Some((pin_index(pins.0) * 10 + pin_index(pins.1), voltage))
+            }
+            _ => None,
+        }
+    }
+
+    impl<F: Filter + Clone> EventSmoother<F> {
+        /// Every measurement gets its own copy of `template`
+        pub fn new(template: F) -> Self {
+            Self {
+                template,
+                filters: HashMap::new(),
+            }
+        }
+
+        /// Replace the measured value of the event with its smoothed value
+        pub fn apply(&mut self, event: &mut Event) {
+            let discriminant = std::mem::discriminant(&event.observation);
+            let Some((index, value)) = measurement(&mut event.observation) else {
+                return;
+            };
+            let filter = self
+                .filters
+                .entry((event.charger.clone(), discriminant, index))
+                .or_insert_with(|| self.template.clone());
+            *value = filter.push(*value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Ema, Filter, Median};
+
+    #[test]
+    fn filters() {
+        let mut ema = Ema::new(0.5);
+        assert_eq!(ema.push(10.0), 10.0);
+        assert_eq!(ema.push(20.0), 15.0);
+
+        let mut median = Median::new(3);
+        assert_eq!(median.push(1.0), 1.0);
+        assert_eq!(median.push(100.0), 50.5);
+        assert_eq!(median.push(2.0), 2.0);
+        assert_eq!(median.push(3.0), 3.0);
+    }
+}
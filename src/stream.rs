@@ -1,13 +1,14 @@
 use super::api::{ApiError, Context};
+use crate::secret::SecretString;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::net::TcpStream;
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
 use thiserror::Error;
-use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
-
-const STREAM_API_NEGOTIATION_URL: &str =
-    "https://streams.easee.com/hubs/products/negotiate?negotiateVersion=1";
-const WSS_URL: &str = "wss://streams.easee.com/hubs/products";
+use tungstenite::{stream::MaybeTlsStream, HandshakeError, Message, WebSocket};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, PartialOrd)]
 #[serde(rename_all = "camelCase")]
@@ -23,7 +24,13 @@ pub enum NegotiateError {
     ApiError(#[from] ApiError),
 
     #[error("WS error: {0}")]
-    TungsteniteError(#[from] tungstenite::Error),
+    TungsteniteError(#[source] Box<tungstenite::Error>),
+}
+
+impl From<tungstenite::Error> for NegotiateError {
+    fn from(e: tungstenite::Error) -> Self {
+        NegotiateError::TungsteniteError(Box::new(e))
+    }
 }
 
 #[derive(Debug, Error)]
@@ -35,24 +42,59 @@ pub enum RecvError {
     InvalidJson(#[from] serde_json::Error),
 
     #[error("WS error: {0}")]
-    TungsteniteError(#[from] tungstenite::Error),
+    TungsteniteError(#[source] Box<tungstenite::Error>),
 }
 
-pub struct Stream {
-    sock: WebSocket<MaybeTlsStream<TcpStream>>,
+impl From<tungstenite::Error> for RecvError {
+    fn from(e: tungstenite::Error) -> Self {
+        RecvError::TungsteniteError(Box::new(e))
+    }
 }
 
-impl Stream {
-    pub fn open(ctx: &mut Context) -> Result<Stream, NegotiateError> {
-        let r: NegotiateResponse = ctx.post_raw(STREAM_API_NEGOTIATION_URL, &())?;
+impl RecvError {
+    /// Whether nothing was received before the read timeout, see
+    /// [`Stream::set_read_timeout`]. The stream can still be read from.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            RecvError::TungsteniteError(e) => match &**e {
+                tungstenite::Error::Io(e) => {
+                    matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    )
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
 
-        let token = ctx.auth_token();
-        let wss_url = format!(
-            "{}?id={}&access_token={}",
-            WSS_URL, r.connection_token, token
-        );
+/// Websocket to the stream API. By default over a TCP connection opened by
+/// the crate, but any transport can be provided with [`Stream::open_with`].
+pub struct Stream<S = MaybeTlsStream<TcpStream>> {
+    sock: WebSocket<S>,
+}
 
-        let resp = tungstenite::client::connect(&wss_url);
+/// Negotiate a connection, returning the websocket URL
+fn negotiate(ctx: &mut Context) -> Result<SecretString, ApiError> {
+    let config = ctx.client_config().clone();
+    let r: NegotiateResponse =
+        ctx.post_raw(&config.stream_negotiate_url, "hubs/products/negotiate", &())?;
+
+    // The URL embeds the access token, keep it out of logs
+    Ok(SecretString::from(format!(
+        "{}?id={}&access_token={}",
+        config.stream_wss_url,
+        r.connection_token,
+        ctx.auth_token()
+    )))
+}
+
+impl Stream {
+    pub fn open(ctx: &mut Context) -> Result<Stream, NegotiateError> {
+        let wss_url = negotiate(ctx)?;
+        let resp = tungstenite::client::connect(wss_url.expose());
 
         if let Err(tungstenite::Error::Http(he)) = &resp {
             eprintln!(
@@ -61,9 +103,40 @@ impl Stream {
             );
         }
 
-        let mut stream = Stream { sock: resp?.0 };
-        stream.send(json!({ "protocol": "json", "version": 1 }))?;
+        Stream::handshake(resp?.0)
+    }
+
+    /// Give up waiting for a message after `timeout`, so that the reading
+    /// thread gets a chance to do something else, such as stopping
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let tcp = match self.sock.get_ref() {
+            MaybeTlsStream::Plain(tcp) => tcp,
+            MaybeTlsStream::Rustls(tls) => &tls.sock,
+            _ => return Ok(()),
+        };
+        tcp.set_read_timeout(timeout)
+    }
+}
 
+impl<S: Read + Write> Stream<S> {
+    /// Run the websocket handshake over `transport`, a connection to
+    /// streams.easee.com established by the caller, such as through a SOCKS
+    /// tunnel or with a custom TLS stack. TLS, if any, must already be set up.
+    pub fn open_with(ctx: &mut Context, transport: S) -> Result<Self, NegotiateError> {
+        let wss_url = negotiate(ctx)?;
+        let sock = match tungstenite::client::client(wss_url.expose(), transport) {
+            Ok((sock, _)) => sock,
+            Err(HandshakeError::Failure(e)) => return Err(e.into()),
+            Err(HandshakeError::Interrupted(_)) => {
+                return Err(tungstenite::Error::Io(std::io::ErrorKind::WouldBlock.into()).into())
+            }
+        };
+        Self::handshake(sock)
+    }
+
+    fn handshake(sock: WebSocket<S>) -> Result<Self, NegotiateError> {
+        let mut stream = Stream { sock };
+        stream.send(json!({ "protocol": "json", "version": 1 }))?;
         Ok(stream)
     }
 
@@ -73,6 +146,12 @@ impl Stream {
         self.sock.send(Message::Text(msg))
     }
 
+    /// Close the websocket cleanly
+    pub fn close(&mut self) -> Result<(), RecvError> {
+        self.sock.close(None)?;
+        Ok(self.sock.flush()?)
+    }
+
     pub fn recv(&mut self) -> Result<Vec<serde_json::Value>, RecvError> {
         let msg = self.sock.read()?;
         let Message::Text(txt) = msg else {
@@ -0,0 +1,230 @@
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+use crate::api::{ApiError, Charger, Context, PhaseMode};
+
+/// Number of phases used for charging
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phases {
+    Single,
+    Three,
+}
+
+impl Phases {
+    pub fn count(self) -> f64 {
+        match self {
+            Phases::Single => 1.0,
+            Phases::Three => 3.0,
+        }
+    }
+
+    pub fn mode(self) -> PhaseMode {
+        match self {
+            Phases::Single => PhaseMode::Phase1,
+            Phases::Three => PhaseMode::Phase2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SurplusConfig {
+    /// Phase-to-neutral voltage, in V
+    pub voltage: f64,
+
+    /// Minimum current per phase, in A. EVs will not charge below 6A.
+    pub min_current: f64,
+
+    /// Maximum current per phase, in A
+    pub max_current: f64,
+
+    /// Surplus required above the three-phase minimum before switching to
+    /// three phases, in W
+    pub hysteresis: f64,
+
+    /// Minimum delay between two phase switches
+    pub min_switch_interval: Duration,
+}
+
+impl Default for SurplusConfig {
+    fn default() -> Self {
+        Self {
+            voltage: 230.0,
+            min_current: 6.0,
+            max_current: 16.0,
+            hysteresis: 300.0,
+            min_switch_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    /// Not enough surplus to charge at all
+    Stop,
+
+    /// Keep the current phase count, with the given per-phase current
+    Charge { current: f64 },
+
+    /// Change the phase count, then charge with the given per-phase current
+    Switch { phases: Phases, current: f64 },
+}
+
+/// Decides between single-phase and three-phase charging based on available surplus power
+#[derive(Clone, Debug)]
+pub struct PhaseSwitcher {
+    config: SurplusConfig,
+    phases: Phases,
+    last_switch: Option<Instant>,
+}
+
+impl PhaseSwitcher {
+    pub fn new(config: SurplusConfig, phases: Phases) -> Self {
+        Self {
+            config,
+            phases,
+            last_switch: None,
+        }
+    }
+
+    pub fn phases(&self) -> Phases {
+        self.phases
+    }
+
+    fn min_power(&self, phases: Phases) -> f64 {
+        self.config.voltage * self.config.min_current * phases.count()
+    }
+
+    fn current_for(&self, surplus: f64, phases: Phases) -> f64 {
+        (surplus / (self.config.voltage * phases.count())).min(self.config.max_current)
+    }
+
+    /// Compute the action to take given the current surplus power, in W
+    pub fn update(&mut self, surplus: f64, now: Instant) -> Decision {
+        let can_switch = self
+            .last_switch
+            .is_none_or(|t| now.duration_since(t) >= self.config.min_switch_interval);
+
+        // The hysteresis only delays going to three phases: below the
+        // three-phase minimum, single phase still uses the surplus
+        let target = match self.phases {
+            Phases::Single if surplus >= self.min_power(Phases::Three) + self.config.hysteresis => {
+                Phases::Three
+            }
+            Phases::Three if surplus < self.min_power(Phases::Three) => Phases::Single,
+            current => current,
+        };
+
+        if target != self.phases && can_switch {
+            // Without enough surplus to charge after the switch, stop and
+            // keep the phases the charger actually uses
+            let current = self.current_for(surplus, target);
+            if current < self.config.min_current {
+                return Decision::Stop;
+            }
+            info!("Switching from {:?} to {:?}", self.phases, target);
+            self.phases = target;
+            self.last_switch = Some(now);
+            return Decision::Switch {
+                phases: target,
+                current,
+            };
+        }
+
+        let current = self.current_for(surplus, self.phases);
+        if current < self.config.min_current {
+            Decision::Stop
+        } else {
+            Decision::Charge { current }
+        }
+    }
+}
+
+/// Change the phase mode of a charger. Charging is paused during the change,
+/// as the car would otherwise see the phases disappear under load.
+pub fn switch_phases(ctx: &mut Context, charger: &Charger, phases: Phases) -> Result<(), ApiError> {
+    charger.pause(ctx)?;
+    charger.set_phase_mode(ctx, phases.mode())?;
+    charger.resume(ctx)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{Decision, PhaseSwitcher, Phases, SurplusConfig};
+
+    #[test]
+    fn hysteresis() {
+        let mut sw = PhaseSwitcher::new(SurplusConfig::default(), Phases::Single);
+        let t0 = Instant::now();
+
+        assert_eq!(sw.update(1000.0, t0), Decision::Stop);
+        assert!(matches!(sw.update(3000.0, t0), Decision::Charge { .. }));
+
+        // Just above the 3-phase minimum, but within the hysteresis band
+        assert!(matches!(sw.update(4200.0, t0), Decision::Charge { .. }));
+        assert!(matches!(
+            sw.update(5000.0, t0),
+            Decision::Switch {
+                phases: Phases::Three,
+                ..
+            }
+        ));
+
+        // Too soon to switch back
+        let t1 = t0 + Duration::from_secs(10);
+        assert_eq!(sw.update(3000.0, t1), Decision::Stop);
+        assert_eq!(sw.phases(), Phases::Three);
+
+        let t2 = t0 + Duration::from_secs(600);
+        assert!(matches!(
+            sw.update(3000.0, t2),
+            Decision::Switch {
+                phases: Phases::Single,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn single_phase_below_three_phase_minimum() {
+        let mut sw = PhaseSwitcher::new(SurplusConfig::default(), Phases::Three);
+        let t0 = Instant::now();
+
+        // Within the hysteresis band below the 4140 W three-phase minimum
+        assert_eq!(
+            sw.update(3900.0, t0),
+            Decision::Switch {
+                phases: Phases::Single,
+                current: 16.0
+            }
+        );
+
+        // Back above the minimum, but not above the hysteresis
+        let t1 = t0 + Duration::from_secs(600);
+        assert_eq!(sw.update(4200.0, t1), Decision::Charge { current: 16.0 });
+        assert_eq!(sw.phases(), Phases::Single);
+    }
+
+    #[test]
+    fn stop_keeps_phases() {
+        let mut sw = PhaseSwitcher::new(SurplusConfig::default(), Phases::Three);
+        let t0 = Instant::now();
+
+        // Too little to charge even on a single phase: the charger stops,
+        // without being told to switch
+        assert_eq!(sw.update(1000.0, t0), Decision::Stop);
+        assert_eq!(sw.phases(), Phases::Three);
+
+        // Enough for a single phase, which it is now told to switch to
+        assert_eq!(
+            sw.update(3000.0, t0),
+            Decision::Switch {
+                phases: Phases::Single,
+                current: 3000.0 / 230.0
+            }
+        );
+        assert_eq!(sw.phases(), Phases::Single);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::api::TokenParseError;
+use crate::secret::SecretString;
+
+#[derive(Debug, Error)]
+pub enum TokenStoreError {
+    #[error("io: {0}")]
+    IO(#[from] io::Error),
+
+    #[error("invalid stored tokens: {0}")]
+    Parse(#[from] TokenParseError),
+
+    #[cfg(feature = "keyring")]
+    #[error("keyring: {0}")]
+    Keyring(#[from] keyring::Error),
+}
+
+/// Persistent storage for the tokens of a [`crate::api::Context`]. Tokens
+/// are handed over serialized, in the JSON format of
+/// [`crate::api::Context::save_pyeasee`].
+pub trait TokenStore: Send {
+    /// Read the stored tokens, if any
+    fn load(&mut self) -> Result<Option<SecretString>, TokenStoreError>;
+
+    /// Replace the stored tokens
+    fn save(&mut self, tokens: &SecretString) -> Result<(), TokenStoreError>;
+}
+
+/// Tokens stored in a JSON file, readable only by the current user
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+        }
+    }
+}
+
+impl TokenStore for FileStore {
+    fn load(&mut self) -> Result<Option<SecretString>, TokenStoreError> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(Some(content.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, tokens: &SecretString) -> Result<(), TokenStoreError> {
+        // Write aside and rename, so that a crash never leaves a truncated file
+        let tmp = self.path.with_extension("tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        io::Write::write_all(&mut options.open(&tmp)?, tokens.expose().as_bytes())?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Tokens stored in the keyring of the OS: Keychain on macOS, the credential
+/// manager on Windows, and the kernel keyutils on Linux
+#[cfg(feature = "keyring")]
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    /// Entry for `user` under `service`, such as `("easee", "me@example.com")`
+    pub fn new(service: &str, user: &str) -> Result<Self, TokenStoreError> {
+        Ok(Self {
+            entry: keyring::Entry::new(service, user)?,
+        })
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl TokenStore for KeyringStore {
+    fn load(&mut self) -> Result<Option<SecretString>, TokenStoreError> {
+        match self.entry.get_password() {
+            Ok(tokens) => Ok(Some(tokens.into())),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, tokens: &SecretString) -> Result<(), TokenStoreError> {
+        Ok(self.entry.set_password(tokens.expose())?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileStore, TokenStore};
+
+    #[test]
+    fn file_store_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("easee-rs-test-{}.tokens", std::process::id()));
+        let mut store = FileStore::new(&path);
+        assert!(store.load().unwrap().is_none());
+
+        store.save(&"{\"accessToken\":\"a\"}".into()).unwrap();
+        assert_eq!(
+            store.load().unwrap().unwrap().expose(),
+            "{\"accessToken\":\"a\"}"
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Declare a physical quantity wrapping an `f64`, with the arithmetic that
+/// keeps its unit: sums and differences of the same quantity, and scaling.
+macro_rules! unit {
+    ($(#[$doc:meta])* $name:ident, $symbol:literal) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Deserialize, Serialize)]
+        #[serde(transparent)]
+        pub struct $name(pub f64);
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, rhs: $name) -> $name {
+                $name(self.0 + rhs.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, rhs: $name) -> $name {
+                $name(self.0 - rhs.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: $name) {
+                self.0 += rhs.0;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: $name) {
+                self.0 -= rhs.0;
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+
+            fn neg(self) -> $name {
+                $name(-self.0)
+            }
+        }
+
+        impl Mul<f64> for $name {
+            type Output = $name;
+
+            fn mul(self, rhs: f64) -> $name {
+                $name(self.0 * rhs)
+            }
+        }
+
+        impl Div<f64> for $name {
+            type Output = $name;
+
+            fn div(self, rhs: f64) -> $name {
+                $name(self.0 / rhs)
+            }
+        }
+
+        /// Ratio of two quantities of the same unit
+        impl Div for $name {
+            type Output = f64;
+
+            fn div(self, rhs: $name) -> f64 {
+                self.0 / rhs.0
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = $name>>(iter: I) -> $name {
+                $name(iter.map(|v| v.0).sum())
+            }
+        }
+
+        impl<'a> Sum<&'a $name> for $name {
+            fn sum<I: Iterator<Item = &'a $name>>(iter: I) -> $name {
+                iter.copied().sum()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)?;
+                f.write_str(concat!(" ", $symbol))
+            }
+        }
+    };
+}
+
+unit! {
+    /// Electric current
+    Amperes, "A"
+}
+
+unit! {
+    /// Power, as drawn by a charger
+    Kilowatts, "kW"
+}
+
+unit! {
+    /// Energy, as consumed by a charger
+    KilowattHours, "kWh"
+}
+
+impl Kilowatts {
+    /// Energy consumed at this power for `hours`
+    pub fn over_hours(self, hours: f64) -> KilowattHours {
+        KilowattHours(self.0 * hours)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Amperes, KilowattHours, Kilowatts};
+
+    #[test]
+    fn unit_arithmetic() {
+        let total: Amperes = [Amperes(16.0), Amperes(10.0)].iter().sum();
+        assert_eq!(total - Amperes(6.0), Amperes(20.0));
+        assert_eq!(Amperes(8.0) / Amperes(32.0), 0.25);
+        assert_eq!(Kilowatts(11.0).over_hours(0.5), KilowattHours(5.5));
+        assert_eq!(format!("{:.1}", KilowattHours(2.26)), "2.3 kWh");
+        assert_eq!(serde_json::to_string(&Amperes(6.0)).unwrap(), "6.0");
+    }
+}
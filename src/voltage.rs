@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use crate::observation::InputPin::{self, *};
+
+const PAIRS: [(InputPin, InputPin); 10] = [
+    (T1, T2),
+    (T1, T3),
+    (T1, T4),
+    (T1, T5),
+    (T2, T3),
+    (T2, T4),
+    (T2, T5),
+    (T3, T4),
+    (T3, T5),
+    (T4, T5),
+];
+
+/// Below this voltage, a pair of input pins is considered unconnected
+const CONNECTED: f64 = 100.0;
+
+/// Above this voltage, a pair of input pins is across two phases of a 400V grid
+const LINE_TO_LINE_400: f64 = 320.0;
+
+/// Kind of grid the charger is connected to, guessed from its input voltages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridType {
+    /// Three phases and neutral, 400V between phases (TN/TT)
+    ThreePhase400,
+
+    /// Three phases without neutral, 230V between phases (IT)
+    ThreePhase230,
+
+    /// One phase and neutral, or two phases of an IT grid
+    SinglePhase,
+
+    Unknown,
+}
+
+/// Voltages measured between each pair of input pins, in V
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub struct VoltageMatrix {
+    pub in_voltage_t1_t2: Option<f64>,
+    pub in_voltage_t1_t3: Option<f64>,
+    pub in_voltage_t1_t4: Option<f64>,
+    pub in_voltage_t1_t5: Option<f64>,
+    pub in_voltage_t2_t3: Option<f64>,
+    pub in_voltage_t2_t4: Option<f64>,
+    pub in_voltage_t2_t5: Option<f64>,
+    pub in_voltage_t3_t4: Option<f64>,
+    pub in_voltage_t3_t5: Option<f64>,
+    pub in_voltage_t4_t5: Option<f64>,
+}
+
+fn pin_index(pin: InputPin) -> usize {
+    match pin {
+        T1 => 0,
+        T2 => 1,
+        T3 => 2,
+        T4 => 3,
+        T5 => 4,
+    }
+}
+
+impl VoltageMatrix {
+    fn slot(&mut self, index: usize) -> &mut Option<f64> {
+        [
+            &mut self.in_voltage_t1_t2,
+            &mut self.in_voltage_t1_t3,
+            &mut self.in_voltage_t1_t4,
+            &mut self.in_voltage_t1_t5,
+            &mut self.in_voltage_t2_t3,
+            &mut self.in_voltage_t2_t4,
+            &mut self.in_voltage_t2_t5,
+            &mut self.in_voltage_t3_t4,
+            &mut self.in_voltage_t3_t5,
+            &mut self.in_voltage_t4_t5,
+        ]
+        .into_iter()
+        .nth(index)
+        .unwrap()
+    }
+
+    fn values(&self) -> [Option<f64>; 10] {
+        [
+            self.in_voltage_t1_t2,
+            self.in_voltage_t1_t3,
+            self.in_voltage_t1_t4,
+            self.in_voltage_t1_t5,
+            self.in_voltage_t2_t3,
+            self.in_voltage_t2_t4,
+            self.in_voltage_t2_t5,
+            self.in_voltage_t3_t4,
+            self.in_voltage_t3_t5,
+            self.in_voltage_t4_t5,
+        ]
+    }
+
+    fn index(a: InputPin, b: InputPin) -> Option<usize> {
+        let (a, b) = (pin_index(a), pin_index(b));
+        let (a, b) = (a.min(b), a.max(b));
+        PAIRS
+            .iter()
+            .position(|&(x, y)| pin_index(x) == a && pin_index(y) == b)
+    }
+
+    /// Voltage between two pins, in any order
+    pub fn get(&self, a: InputPin, b: InputPin) -> Option<f64> {
+        Self::index(a, b).and_then(|i| self.values()[i])
+    }
+
+    /// Record the voltage between two pins, as reported by an `IntVoltage` observation
+    pub fn set(&mut self, a: InputPin, b: InputPin, voltage: f64) {
+        if let Some(i) = Self::index(a, b) {
+            *self.slot(i) = Some(voltage);
+        }
+    }
+
+    /// Measured voltages with their pair of pins
+    pub fn iter(&self) -> impl Iterator<Item = ((InputPin, InputPin), f64)> {
+        PAIRS
+            .into_iter()
+            .zip(self.values())
+            .filter_map(|(pins, v)| Some((pins, v?)))
+    }
+
+    /// Guess the grid type from the pattern of measured voltages
+    pub fn grid_type(&self) -> GridType {
+        let live: Vec<f64> = self
+            .iter()
+            .map(|(_, v)| v)
+            .filter(|&v| v >= CONNECTED)
+            .collect();
+        let high = live.iter().filter(|&&v| v >= LINE_TO_LINE_400).count();
+        match (live.len(), high) {
+            (0, _) => GridType::Unknown,
+            (_, h) if h >= 3 => GridType::ThreePhase400,
+            (n, 0) if n >= 3 => GridType::ThreePhase230,
+            (_, 0) => GridType::SinglePhase,
+            _ => GridType::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GridType, VoltageMatrix};
+    use crate::observation::InputPin::*;
+
+    #[test]
+    fn grid_detection() {
+        let mut tn = VoltageMatrix::default();
+        tn.set(T2, T3, 400.0);
+        tn.set(T2, T4, 401.0);
+        tn.set(T4, T3, 399.0);
+        for p in [T2, T3, T4] {
+            tn.set(p, T5, 231.0);
+        }
+        assert_eq!(tn.get(T3, T2), Some(400.0));
+        assert_eq!(tn.iter().count(), 6);
+        assert_eq!(tn.grid_type(), GridType::ThreePhase400);
+
+        let mut it = VoltageMatrix::default();
+        it.set(T2, T3, 232.0);
+        it.set(T2, T4, 229.0);
+        it.set(T3, T4, 230.0);
+        assert_eq!(it.grid_type(), GridType::ThreePhase230);
+
+        let mut single = VoltageMatrix::default();
+        single.set(T2, T3, 230.0);
+        single.set(T1, T4, 0.5);
+        assert_eq!(single.grid_type(), GridType::SinglePhase);
+        assert_eq!(VoltageMatrix::default().grid_type(), GridType::Unknown);
+    }
+}